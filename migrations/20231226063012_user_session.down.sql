@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE `user` ADD COLUMN `refresh_token` varchar(4096) AFTER `deleted`;
+
+DROP TABLE `user_session`;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+ALTER TABLE `user_session`
+  DROP COLUMN `access_token_id`,
+  DROP COLUMN `access_token_expires_at`
+;
+
+DROP TABLE `revoked_token`;
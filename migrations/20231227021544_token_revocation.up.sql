@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+CREATE TABLE `revoked_token` (
+  `id` char(32) NOT NULL,
+  `user_id` int(10) unsigned NOT NULL,
+  `expires_at` timestamp NOT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  CONSTRAINT `revoked_token_user` FOREIGN KEY (`user_id`) REFERENCES `user` (`id`) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+ALTER TABLE `user_session`
+  ADD COLUMN `access_token_id` char(32) AFTER `refresh_token`,
+  ADD COLUMN `access_token_expires_at` timestamp NULL AFTER `access_token_id`
+;
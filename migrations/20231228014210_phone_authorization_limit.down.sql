@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP TABLE `phone_authorization_log`;
+
+ALTER TABLE `phone_authorization` DROP COLUMN `failed_attempts`;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+ALTER TABLE `phone_authorization` ADD COLUMN `failed_attempts` int(10) unsigned NOT NULL DEFAULT 0 AFTER `code`;
+
+CREATE TABLE `phone_authorization_log` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `phone` char(13) NOT NULL,
+  `ip_address` varchar(45),
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  KEY `phone_authorization_log_phone` (`phone`, `created_at`),
+  KEY `phone_authorization_log_ip_address` (`ip_address`, `created_at`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
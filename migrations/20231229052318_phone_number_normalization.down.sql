@@ -0,0 +1,12 @@
+-- Add down migration script here
+
+DELETE FROM `phone_authorization`;
+
+DROP TABLE `user_phone_collision`;
+
+UPDATE IGNORE `user` SET `phone` = CONCAT('0', SUBSTRING(`phone`, 4)) WHERE `phone` LIKE '+82%';
+UPDATE `phone_authorization_log` SET `phone` = CONCAT('0', SUBSTRING(`phone`, 4)) WHERE `phone` LIKE '+82%';
+
+ALTER TABLE `user` MODIFY COLUMN `phone` char(13) NOT NULL;
+ALTER TABLE `phone_authorization` MODIFY COLUMN `phone` char(13) NOT NULL;
+ALTER TABLE `phone_authorization_log` MODIFY COLUMN `phone` char(13) NOT NULL;
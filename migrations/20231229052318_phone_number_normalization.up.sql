@@ -0,0 +1,49 @@
+-- Add up migration script here
+
+ALTER TABLE `user` MODIFY COLUMN `phone` varchar(16) NOT NULL;
+ALTER TABLE `phone_authorization` MODIFY COLUMN `phone` varchar(16) NOT NULL;
+ALTER TABLE `phone_authorization_log` MODIFY COLUMN `phone` varchar(16) NOT NULL;
+
+-- Pending verification codes are short-lived, so they are dropped instead of converted
+DELETE FROM `phone_authorization`;
+
+-- Normalize phone numbers to the E.164 format.
+-- Korean national numbers (leading 0) get the +82 country code, and every
+-- other number is assumed to contain its country code already.
+-- The normalized number is computed at once into a temporary column, so that
+-- every row is either converted fully or left as it is.
+ALTER TABLE `user` ADD COLUMN `normalized_phone` varchar(16) NULL;
+
+UPDATE `user` SET `normalized_phone` = CASE
+  WHEN REGEXP_REPLACE(`phone`, '[^0-9+]', '') LIKE '+%' THEN REGEXP_REPLACE(`phone`, '[^0-9+]', '')
+  WHEN REGEXP_REPLACE(`phone`, '[^0-9+]', '') LIKE '0%' THEN CONCAT('+82', SUBSTRING(REGEXP_REPLACE(`phone`, '[^0-9+]', ''), 2))
+  ELSE CONCAT('+', REGEXP_REPLACE(`phone`, '[^0-9+]', ''))
+END;
+
+-- Accounts whose numbers normalize to the same one are kept here with their
+-- original numbers, and left unconverted for manual review
+CREATE TABLE `user_phone_collision` (
+  `user_id` int(10) unsigned NOT NULL,
+  `phone` varchar(16) NOT NULL,
+  `normalized_phone` varchar(16) NOT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`user_id`),
+  KEY `user_phone_collision_normalized_phone` (`normalized_phone`),
+  CONSTRAINT `user_phone_collision_user` FOREIGN KEY (`user_id`) REFERENCES `user` (`id`) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+INSERT INTO `user_phone_collision` (user_id, phone, normalized_phone)
+SELECT u.id, u.phone, u.normalized_phone FROM `user` as u WHERE u.normalized_phone IN (
+  SELECT normalized_phone FROM `user` GROUP BY normalized_phone HAVING COUNT(*) > 1
+);
+
+UPDATE `user` SET `phone` = `normalized_phone`
+WHERE `phone` <> `normalized_phone` AND id NOT IN (SELECT user_id FROM `user_phone_collision`);
+
+ALTER TABLE `user` DROP COLUMN `normalized_phone`;
+
+UPDATE `phone_authorization_log` SET `phone` = CASE
+  WHEN REGEXP_REPLACE(`phone`, '[^0-9+]', '') LIKE '+%' THEN REGEXP_REPLACE(`phone`, '[^0-9+]', '')
+  WHEN REGEXP_REPLACE(`phone`, '[^0-9+]', '') LIKE '0%' THEN CONCAT('+82', SUBSTRING(REGEXP_REPLACE(`phone`, '[^0-9+]', ''), 2))
+  ELSE CONCAT('+', REGEXP_REPLACE(`phone`, '[^0-9+]', ''))
+END;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+
+ALTER TABLE `user` ADD COLUMN `role` int(10) unsigned NOT NULL DEFAULT 1 AFTER `town_id`;
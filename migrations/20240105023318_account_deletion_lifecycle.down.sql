@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP TABLE `account_deletion_log`;
+
+ALTER TABLE `user` DROP COLUMN `deleted_at`;
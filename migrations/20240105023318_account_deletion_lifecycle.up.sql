@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+ALTER TABLE `user` ADD COLUMN `deleted_at` timestamp NULL AFTER `deleted`;
+UPDATE `user` SET `deleted_at` = `updated_at` WHERE `deleted` = TRUE;
+
+-- Not tied to `user` so that the log outlives the purged account
+CREATE TABLE `account_deletion_log` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `user_id` int(10) unsigned NOT NULL,
+  `step` int(10) unsigned NOT NULL,
+  `detail` varchar(256),
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  KEY `account_deletion_log_user` (`user_id`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
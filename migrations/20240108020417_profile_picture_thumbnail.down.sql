@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE `user`
+  DROP COLUMN `picture_medium`,
+  DROP COLUMN `picture_small`;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+ALTER TABLE `user`
+  ADD COLUMN `picture_medium` varchar(4096) AFTER `picture`,
+  ADD COLUMN `picture_small` varchar(4096) AFTER `picture_medium`;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+ALTER TABLE `user`
+  DROP COLUMN `show_birthdate`,
+  DROP COLUMN `show_phone`,
+  DROP COLUMN `show_total_likes`;
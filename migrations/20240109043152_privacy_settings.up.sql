@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+ALTER TABLE `user`
+  ADD COLUMN `show_birthdate` BOOL NOT NULL DEFAULT FALSE AFTER `deleted_at`,
+  ADD COLUMN `show_phone` BOOL NOT NULL DEFAULT FALSE AFTER `show_birthdate`,
+  ADD COLUMN `show_total_likes` BOOL NOT NULL DEFAULT TRUE AFTER `show_phone`;
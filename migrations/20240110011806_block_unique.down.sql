@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+-- The foreign keys on `user_id` may be using the unique keys as their index
+ALTER TABLE `user_block` ADD KEY `user_block_user_id` (`user_id`), DROP INDEX `unique_user_block`;
+ALTER TABLE `post_block` ADD KEY `post_block_user_id` (`user_id`), DROP INDEX `unique_post_block`;
+ALTER TABLE `post_comment_block`
+  ADD KEY `post_comment_block_user_id` (`user_id`),
+  DROP INDEX `unique_post_comment_block`;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+-- Keep the oldest row of every duplicated block before adding the unique keys
+DELETE b1 FROM `user_block` b1
+INNER JOIN `user_block` b2 ON b1.user_id = b2.user_id AND b1.target_id = b2.target_id AND b1.id > b2.id;
+DELETE b1 FROM `post_block` b1
+INNER JOIN `post_block` b2 ON b1.user_id = b2.user_id AND b1.post_id = b2.post_id AND b1.id > b2.id;
+DELETE b1 FROM `post_comment_block` b1
+INNER JOIN `post_comment_block` b2 ON b1.user_id = b2.user_id AND b1.comment_id = b2.comment_id AND b1.id > b2.id;
+
+ALTER TABLE `user_block` ADD UNIQUE KEY `unique_user_block` (`user_id`, `target_id`);
+ALTER TABLE `post_block` ADD UNIQUE KEY `unique_post_block` (`user_id`, `post_id`);
+ALTER TABLE `post_comment_block` ADD UNIQUE KEY `unique_post_comment_block` (`user_id`, `comment_id`);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+ALTER TABLE `user`
+  DROP FOREIGN KEY `user_verification_reviewer`,
+  DROP COLUMN `verification_submitted_at`,
+  DROP COLUMN `verification_reviewer_id`,
+  DROP COLUMN `verification_reviewed_at`;
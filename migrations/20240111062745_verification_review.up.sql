@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+ALTER TABLE `user`
+  ADD COLUMN `verification_submitted_at` timestamp NULL AFTER `verification_picture_url`,
+  ADD COLUMN `verification_reviewer_id` int(10) unsigned AFTER `verification_submitted_at`,
+  ADD COLUMN `verification_reviewed_at` timestamp NULL AFTER `verification_reviewer_id`,
+  ADD CONSTRAINT `user_verification_reviewer` FOREIGN KEY (`verification_reviewer_id`) REFERENCES `user` (`id`) ON DELETE SET NULL;
+
+UPDATE `user` SET `verification_submitted_at` = `updated_at`
+WHERE `verification_picture_url` IS NOT NULL AND `verification_result` = 0;
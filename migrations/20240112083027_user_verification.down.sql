@@ -0,0 +1,26 @@
+-- Add down migration script here
+
+ALTER TABLE `user`
+  ADD COLUMN `verification_result` int(10) unsigned NOT NULL DEFAULT 0 AFTER `town_id`,
+  ADD COLUMN `verification_type` int(10) unsigned AFTER `verification_result`,
+  ADD COLUMN `verification_picture_url` varchar(4096) AFTER `verification_type`,
+  ADD COLUMN `verification_submitted_at` timestamp NULL AFTER `verification_picture_url`,
+  ADD COLUMN `verification_reviewer_id` int(10) unsigned AFTER `verification_submitted_at`,
+  ADD COLUMN `verification_reviewed_at` timestamp NULL AFTER `verification_reviewer_id`;
+
+-- The bucket is not known here, so the picture URL cannot be restored from the key
+UPDATE `user` AS u
+JOIN `user_verification` AS uv ON uv.`id` = (
+  SELECT MAX(`id`) FROM `user_verification` WHERE `user_id` = u.`id`
+)
+SET
+  u.`verification_result` = uv.`result`,
+  u.`verification_type` = uv.`verification_type`,
+  u.`verification_submitted_at` = uv.`submitted_at`,
+  u.`verification_reviewer_id` = uv.`reviewer_id`,
+  u.`verification_reviewed_at` = uv.`reviewed_at`;
+
+DROP TABLE `user_verification`;
+
+ALTER TABLE `user`
+  ADD CONSTRAINT `user_verification_reviewer` FOREIGN KEY (`verification_reviewer_id`) REFERENCES `user` (`id`) ON DELETE SET NULL;
@@ -0,0 +1,49 @@
+-- Add up migration script here
+
+-- The constraint name is reused by `user_verification`
+ALTER TABLE `user` DROP FOREIGN KEY `user_verification_reviewer`;
+
+CREATE TABLE `user_verification` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `user_id` int(10) unsigned NOT NULL,
+  `verification_type` int(10) unsigned NOT NULL,
+  `picture_key` varchar(4096),
+  `result` int(10) unsigned NOT NULL DEFAULT 0,
+  `reviewer_id` int(10) unsigned,
+  `submitted_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `reviewed_at` timestamp NULL,
+  `picture_deleted_at` timestamp NULL,
+  PRIMARY KEY (`id`),
+  KEY `user_verification_user` (`user_id`),
+  KEY `user_verification_queue` (`result`, `submitted_at`),
+  CONSTRAINT `user_verification_user` FOREIGN KEY (`user_id`) REFERENCES `user` (`id`) ON DELETE CASCADE,
+  CONSTRAINT `user_verification_reviewer` FOREIGN KEY (`reviewer_id`) REFERENCES `user` (`id`) ON DELETE SET NULL
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+-- Only the last submission of each user was kept, so it becomes the first row of the history
+INSERT INTO `user_verification` (
+  `user_id`,
+  `verification_type`,
+  `picture_key`,
+  `result`,
+  `reviewer_id`,
+  `submitted_at`,
+  `reviewed_at`
+)
+SELECT
+  `id`,
+  `verification_type`,
+  SUBSTRING(`verification_picture_url`, LOCATE('verification_photo/', `verification_picture_url`)),
+  `verification_result`,
+  `verification_reviewer_id`,
+  COALESCE(`verification_submitted_at`, `updated_at`),
+  `verification_reviewed_at`
+FROM `user` WHERE `verification_type` IS NOT NULL AND `verification_picture_url` IS NOT NULL;
+
+ALTER TABLE `user`
+  DROP COLUMN `verification_result`,
+  DROP COLUMN `verification_type`,
+  DROP COLUMN `verification_picture_url`,
+  DROP COLUMN `verification_submitted_at`,
+  DROP COLUMN `verification_reviewer_id`,
+  DROP COLUMN `verification_reviewed_at`;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+ALTER TABLE `user_verification` DROP COLUMN `revoked_at`;
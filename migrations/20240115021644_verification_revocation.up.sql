@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+-- Set when a verified user moves to another town and has to verify again
+ALTER TABLE `user_verification` ADD COLUMN `revoked_at` timestamp NULL AFTER `reviewed_at`;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+CREATE TABLE `data_export` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `user_id` int(10) unsigned NOT NULL,
+  `status` int(10) unsigned NOT NULL DEFAULT 1,
+  `archive_key` varchar(4096),
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  `completed_at` timestamp NULL,
+  `expires_at` timestamp NULL,
+  PRIMARY KEY (`id`),
+  KEY `data_export_status` (`status`, `expires_at`),
+  CONSTRAINT `data_export_user` FOREIGN KEY (`user_id`) REFERENCES `user` (`id`) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+ALTER TABLE `post_comment` DROP INDEX `post_comment_search`;
+ALTER TABLE `post` DROP INDEX `post_search`;
@@ -0,0 +1,22 @@
+-- Add up migration script here
+
+-- The ngram parser splits Korean text, which has no spaces between a word and
+-- its particles, into searchable tokens. MariaDB has no ngram parser, so the
+-- indexes fall back to the default whitespace parser there.
+SET @search_parser = IF(VERSION() LIKE '%MariaDB%', '', ' WITH PARSER ngram');
+
+SET @search_index = CONCAT(
+  'ALTER TABLE `post` ADD FULLTEXT INDEX `post_search` (`content`, `place`)',
+  @search_parser
+);
+PREPARE search_index FROM @search_index;
+EXECUTE search_index;
+DEALLOCATE PREPARE search_index;
+
+SET @search_index = CONCAT(
+  'ALTER TABLE `post_comment` ADD FULLTEXT INDEX `post_comment_search` (`content`)',
+  @search_parser
+);
+PREPARE search_index FROM @search_index;
+EXECUTE search_index;
+DEALLOCATE PREPARE search_index;
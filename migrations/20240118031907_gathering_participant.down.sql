@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+UPDATE `gathering_age_range` SET min_age = 20, max_age = 29 WHERE id IN (1, 2);
+
+DROP TABLE `gathering_participant`;
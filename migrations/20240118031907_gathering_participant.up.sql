@@ -0,0 +1,17 @@
+-- Add up migration script here
+
+CREATE TABLE `gathering_participant` (
+  `id` int(10) unsigned NOT NULL AUTO_INCREMENT,
+  `post_id` int(10) unsigned NOT NULL,
+  `user_id` int(10) unsigned NOT NULL,
+  `created_at` timestamp NOT NULL DEFAULT current_timestamp(),
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `unique_gathering_participant` (`post_id`, `user_id`),
+  CONSTRAINT `gathering_participant_post` FOREIGN KEY (`post_id`) REFERENCES `post` (`id`) ON DELETE CASCADE,
+  CONSTRAINT `gathering_participant_user` FOREIGN KEY (`user_id`) REFERENCES `user` (`id`) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+-- The age ranges were seeded as placeholders: '무관' (any age) must not limit
+-- the age and '10대' is for teenagers.
+UPDATE `gathering_age_range` SET min_age = NULL, max_age = NULL WHERE id = 1;
+UPDATE `gathering_age_range` SET min_age = 10, max_age = 19 WHERE id = 2;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+ALTER TABLE `post`
+  DROP KEY `post_gathering_schedule`,
+  DROP COLUMN `closed_at`,
+  DROP COLUMN `timezone`,
+  DROP COLUMN `ends_at`,
+  DROP COLUMN `starts_at`;
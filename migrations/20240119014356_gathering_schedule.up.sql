@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+ALTER TABLE `post`
+  ADD COLUMN `starts_at` timestamp NULL AFTER `place`,
+  ADD COLUMN `ends_at` timestamp NULL AFTER `starts_at`,
+  ADD COLUMN `timezone` varchar(64) AFTER `ends_at`,
+  ADD COLUMN `closed_at` timestamp NULL AFTER `timezone`,
+  ADD KEY `post_gathering_schedule` (`post_type`, `ends_at`);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+ALTER TABLE `post_image` DROP COLUMN `position`;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+ALTER TABLE `post_image` ADD COLUMN `position` int(10) unsigned NOT NULL DEFAULT 0 AFTER `image_url`;
+
+-- Images were shown in the order of their ids
+UPDATE `post_image` SET `position` = `id`;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE `user_session`
+  DROP COLUMN `refresh_token_issued_at`,
+  DROP COLUMN `refresh_token_id`;
@@ -0,0 +1,7 @@
+-- Add up migration script here
+
+-- Identify the latest refresh token, so that only older tokens of the session
+-- are taken as replayed
+ALTER TABLE `user_session`
+  ADD COLUMN `refresh_token_id` char(32) AFTER `refresh_token`,
+  ADD COLUMN `refresh_token_issued_at` timestamp NULL AFTER `refresh_token_id`;
@@ -0,0 +1,22 @@
+-- Add down migration script here
+
+DROP TABLE `post_comment_search_token`;
+DROP TABLE `post_search_token`;
+
+SET @search_parser = IF(VERSION() LIKE '%MariaDB%', '', ' WITH PARSER ngram');
+
+SET @search_index = CONCAT(
+  'ALTER TABLE `post` ADD FULLTEXT INDEX `post_search` (`content`, `place`)',
+  @search_parser
+);
+PREPARE search_index FROM @search_index;
+EXECUTE search_index;
+DEALLOCATE PREPARE search_index;
+
+SET @search_index = CONCAT(
+  'ALTER TABLE `post_comment` ADD FULLTEXT INDEX `post_comment_search` (`content`)',
+  @search_parser
+);
+PREPARE search_index FROM @search_index;
+EXECUTE search_index;
+DEALLOCATE PREPARE search_index;
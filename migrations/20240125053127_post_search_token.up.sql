@@ -0,0 +1,52 @@
+-- Add up migration script here
+
+-- Korean words have their particles attached without a space, so posts are
+-- matched by bigrams, every two adjacent characters of their words. This
+-- deviates from a FULLTEXT index WITH PARSER ngram on purpose: MariaDB has no
+-- ngram parser, and its default full-text parser ignores words shorter than
+-- three characters. The bigrams are kept in tables instead, which work the
+-- same on MySQL and MariaDB.
+ALTER TABLE `post_comment` DROP INDEX `post_comment_search`;
+ALTER TABLE `post` DROP INDEX `post_search`;
+
+CREATE TABLE `post_search_token` (
+  `post_id` int(10) unsigned NOT NULL,
+  `token` char(2) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+  PRIMARY KEY (`post_id`, `token`),
+  KEY `post_search_token_token` (`token`, `post_id`),
+  CONSTRAINT `post_search_token_post` FOREIGN KEY (`post_id`) REFERENCES `post` (`id`) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+CREATE TABLE `post_comment_search_token` (
+  `comment_id` int(10) unsigned NOT NULL,
+  `token` char(2) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+  PRIMARY KEY (`comment_id`, `token`),
+  KEY `post_comment_search_token_token` (`token`, `comment_id`),
+  CONSTRAINT `post_comment_search_token_comment` FOREIGN KEY (`comment_id`) REFERENCES `post_comment` (`id`) ON DELETE CASCADE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_general_ci;
+
+-- Existing texts are split as `search::tokens` does, using every character
+-- position up to the longest text a post can hold
+CREATE TABLE `search_token_digit` (`d` int NOT NULL);
+INSERT INTO `search_token_digit` VALUES (0), (1), (2), (3), (4), (5), (6), (7), (8), (9);
+
+CREATE TABLE `search_token_position` (`n` int NOT NULL, PRIMARY KEY (`n`));
+INSERT INTO `search_token_position`
+SELECT 1 + d1.d + 10 * d2.d + 100 * d3.d + 1000 * d4.d + 10000 * d5.d
+FROM `search_token_digit` as d1, `search_token_digit` as d2, `search_token_digit` as d3,
+  `search_token_digit` as d4, `search_token_digit` as d5;
+
+INSERT IGNORE INTO `post_search_token` (post_id, token)
+SELECT t.id, SUBSTRING(t.text, n.n, 2) FROM (
+  SELECT id, LOWER(content) as text FROM `post`
+  UNION ALL SELECT id, LOWER(place) as text FROM `post` WHERE place IS NOT NULL
+) as t INNER JOIN `search_token_position` as n ON n.n < CHAR_LENGTH(t.text)
+WHERE SUBSTRING(t.text, n.n, 2) NOT REGEXP '[[:space:]]';
+
+INSERT IGNORE INTO `post_comment_search_token` (comment_id, token)
+SELECT c.id, SUBSTRING(LOWER(c.content), n.n, 2) FROM `post_comment` as c
+INNER JOIN `search_token_position` as n ON n.n < CHAR_LENGTH(c.content)
+WHERE SUBSTRING(LOWER(c.content), n.n, 2) NOT REGEXP '[[:space:]]';
+
+DROP TABLE `search_token_position`;
+DROP TABLE `search_token_digit`;
@@ -1,11 +1,11 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
-use std::path::Path;
+use std::{path::Path, time::Duration};
 
 use aws_config::BehaviorVersion;
-use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::{presigning::PresigningConfig, primitives::ByteStream};
 
-use crate::{env::get_env_or_panic, Error, Result};
+use crate::{env::get_env_or_panic, error::BoxDynError, Error, Result};
 
 pub(crate) struct S3Client {
     client: aws_sdk_s3::Client,
@@ -45,9 +45,48 @@ impl S3Client {
         Ok(format!("https://{}.s3.{}.amazonaws.com/{}", self.bucket, self.region, target_path))
     }
 
+    pub async fn get_file(&self, target_path: &str) -> Result<Vec<u8>> {
+        let download =
+            |err: BoxDynError| Error::Download { path: target_path.to_string(), source: err };
+
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(target_path)
+            .send()
+            .await
+            .map_err(|err| download(Box::new(err)))?;
+
+        Ok(object.body.collect().await.map_err(|err| download(Box::new(err)))?.to_vec())
+    }
+
     pub async fn delete_file(&self, target_path: &str) -> Result<String> {
-        self.client.delete_object().bucket(&self.bucket).key(target_path).send().await.unwrap();
+        self.client.delete_object().bucket(&self.bucket).key(target_path).send().await.map_err(
+            |err| Error::DeleteUploaded { path: target_path.to_string(), source: Box::new(err) },
+        )?;
 
         Ok(format!("https://{}.s3.{}.amazonaws.com/{}", self.bucket, self.region, target_path))
     }
+
+    /// Creates a link to the object that stops working after `expires_in`.
+    pub async fn presigned_url(&self, target_path: &str, expires_in: Duration) -> Result<String> {
+        let config =
+            PresigningConfig::expires_in(expires_in).map_err(|err| Error::Unhandled(err.into()))?;
+
+        self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(target_path)
+            .presigned(config)
+            .await
+            .map(|request| request.uri().to_string())
+            .map_err(|err| Error::Unhandled(err.into()))
+    }
+
+    /// Returns the object key of a URL returned by `push_file`, or `None` if
+    /// the URL does not point to this bucket.
+    pub fn key_from_url<'a>(&self, url: &'a str) -> Option<&'a str> {
+        url.strip_prefix(&format!("https://{}.s3.{}.amazonaws.com/", self.bucket, self.region))
+    }
 }
@@ -1,46 +1,140 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
-use std::path::PathBuf;
-
-use jsonwebtoken::{DecodingKey, EncodingKey};
-
-use crate::env::get_env_or_panic;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse,
+        RSAKeyParameters, RSAKeyType,
+    },
+    DecodingKey, EncodingKey,
+};
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+
+use crate::{
+    env::get_env_or_panic,
+    message::{self, MessageProvider},
+};
 
 #[derive(Clone)]
 pub struct Config {
     address: String,
     port: u16,
+    trusted_proxy_hops: usize,
 
     access_token_max_age: i64,
     refresh_token_max_age: i64,
 
-    private_key: RsaKey,
-    public_key: RsaKey,
+    key_set: KeySet,
+
+    message_provider: Arc<dyn MessageProvider>,
+
+    test_accounts_enabled: bool,
+
+    account_deletion_grace_period_days: i64,
+
+    verification_picture_retention_days: i64,
+
+    town_change_interval_days: i64,
+    town_change_requires_verification: bool,
+}
+
+/// RSA keys used for signing and verifying JWTs.
+///
+/// Only the active key signs new tokens. Retired keys are kept for
+/// verification until every token signed with them has expired.
+#[derive(Clone)]
+pub(crate) struct KeySet {
+    signing_key_id: String,
+    signing_key: EncodingKey,
+    verification_keys: Vec<VerificationKey>,
 }
 
 #[derive(Clone)]
-pub(crate) struct RsaKey {
-    encoding_key: EncodingKey,
+struct VerificationKey {
+    id: String,
     decoding_key: DecodingKey,
+    jwk: Jwk,
 }
 
-impl RsaKey {
-    fn from_file(path: &std::path::PathBuf) -> std::io::Result<Self> {
-        match std::fs::read_to_string(path) {
-            Ok(key) => Ok(Self {
-                encoding_key: EncodingKey::from_rsa_pem(key.as_bytes()).unwrap(),
-                decoding_key: DecodingKey::from_rsa_pem(key.as_bytes()).unwrap(),
-            }),
-            Err(err) => Err(err),
+impl KeySet {
+    fn from_env() -> Self {
+        let signing_key_id = get_env_or_panic("RSA_KEY_ID");
+        let private_key_path = PathBuf::from(get_env_or_panic("RSA_PRIVATE_PEM_FILE_PATH"));
+        let signing_key = std::fs::read_to_string(&private_key_path)
+            .map(|key| EncodingKey::from_rsa_pem(key.as_bytes()).unwrap())
+            .expect("Cannot open the private key file");
+
+        let mut verification_keys = vec![VerificationKey::from_file(
+            &signing_key_id,
+            &PathBuf::from(get_env_or_panic("RSA_PUBLIC_PEM_FILE_PATH")),
+        )
+        .expect("Cannot open the public key file")];
+
+        // Retired keys are listed as `kid=path` pairs separated by commas
+        if let Ok(retired_keys) = std::env::var("RSA_RETIRED_PUBLIC_PEM_FILE_PATHS") {
+            for entry in retired_keys.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                let (id, path) = entry
+                    .split_once('=')
+                    .unwrap_or_else(|| panic!("Invalid retired public key entry: {entry}"));
+
+                verification_keys.push(
+                    VerificationKey::from_file(id, Path::new(path))
+                        .unwrap_or_else(|_| panic!("Cannot open the public key file {path}")),
+                );
+            }
         }
+
+        Self { signing_key_id, signing_key, verification_keys }
+    }
+
+    pub(crate) fn signing_key_id(&self) -> &str {
+        &self.signing_key_id
     }
 
-    pub(crate) fn encoding_key(&self) -> &EncodingKey {
-        &self.encoding_key
+    pub(crate) fn signing_key(&self) -> &EncodingKey {
+        &self.signing_key
     }
 
-    pub(crate) fn decoding_key(&self) -> &DecodingKey {
-        &self.decoding_key
+    /// Returns the verification key for `kid`, falling back to the active key
+    /// for tokens issued before key IDs were introduced.
+    pub(crate) fn decoding_key(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        let kid = kid.unwrap_or(&self.signing_key_id);
+
+        self.verification_keys.iter().find(|key| key.id == kid).map(|key| &key.decoding_key)
+    }
+
+    pub(crate) fn jwks(&self) -> JwkSet {
+        JwkSet { keys: self.verification_keys.iter().map(|key| key.jwk.clone()).collect() }
+    }
+}
+
+impl VerificationKey {
+    fn from_file(id: &str, path: &Path) -> std::io::Result<Self> {
+        let key = std::fs::read_to_string(path)?;
+        let public_key = RsaPublicKey::from_public_key_pem(&key).unwrap();
+
+        Ok(Self {
+            id: id.to_string(),
+            decoding_key: DecodingKey::from_rsa_pem(key.as_bytes()).unwrap(),
+            jwk: Jwk {
+                common: CommonParameters {
+                    public_key_use: Some(PublicKeyUse::Signature),
+                    key_algorithm: Some(KeyAlgorithm::RS256),
+                    key_id: Some(id.to_string()),
+                    ..Default::default()
+                },
+                algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+                    e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+                }),
+            },
+        })
     }
 }
 
@@ -51,21 +145,44 @@ impl Config {
         Self {
             address: format!("0.0.0.0:{port}"),
             port,
+            trusted_proxy_hops: get_env_or_panic("TRUSTED_PROXY_HOPS").parse().unwrap(),
 
             access_token_max_age: get_env_or_panic("ACCESS_TOKEN_MAX_AGE").parse().unwrap(),
             refresh_token_max_age: get_env_or_panic("REFRESH_TOKEN_MAX_AGE").parse().unwrap(),
 
-            private_key: RsaKey::from_file(
-                &PathBuf::from(get_env_or_panic("RSA_PRIVATE_PEM_FILE_PATH")).to_path_buf(),
+            key_set: KeySet::from_env(),
+
+            message_provider: message::from_env(),
+
+            test_accounts_enabled: std::env::var("TEST_ACCOUNTS_ENABLED")
+                .is_ok_and(|value| value == "true"),
+
+            account_deletion_grace_period_days: get_env_or_panic(
+                "ACCOUNT_DELETION_GRACE_PERIOD_DAYS",
             )
-            .expect("Cannot open the private key file"),
-            public_key: RsaKey::from_file(
-                &PathBuf::from(get_env_or_panic("RSA_PUBLIC_PEM_FILE_PATH")).to_path_buf(),
+            .parse()
+            .unwrap(),
+
+            verification_picture_retention_days: get_env_or_panic(
+                "VERIFICATION_PICTURE_RETENTION_DAYS",
             )
-            .expect("Cannot open the public key file"),
+            .parse()
+            .unwrap(),
+
+            town_change_interval_days: get_env_or_panic("TOWN_CHANGE_INTERVAL_DAYS")
+                .parse()
+                .unwrap(),
+            town_change_requires_verification: std::env::var("TOWN_CHANGE_REQUIRES_VERIFICATION")
+                .is_ok_and(|value| value == "true"),
         }
     }
 
+    /// Replaces the message provider selected by the environment.
+    pub fn with_message_provider(mut self, message_provider: Arc<dyn MessageProvider>) -> Self {
+        self.message_provider = message_provider;
+        self
+    }
+
     pub fn address(&self) -> &str {
         &self.address
     }
@@ -74,12 +191,14 @@ impl Config {
         self.port
     }
 
-    pub fn private_key(&self) -> &EncodingKey {
-        self.private_key.encoding_key()
+    /// Number of reverse proxies in front of the server, each of which appends
+    /// the address it received the request from to `X-Forwarded-For`.
+    pub fn trusted_proxy_hops(&self) -> usize {
+        self.trusted_proxy_hops
     }
 
-    pub fn public_key(&self) -> &DecodingKey {
-        self.public_key.decoding_key()
+    pub(crate) fn key_set(&self) -> &KeySet {
+        &self.key_set
     }
 
     pub fn access_token_max_age(&self) -> i64 {
@@ -89,6 +208,34 @@ impl Config {
     pub fn refresh_token_max_age(&self) -> i64 {
         self.refresh_token_max_age
     }
+
+    pub(crate) fn message_provider(&self) -> &dyn MessageProvider {
+        self.message_provider.as_ref()
+    }
+
+    pub fn test_accounts_enabled(&self) -> bool {
+        self.test_accounts_enabled
+    }
+
+    /// Days a deleted account can still be restored before it is purged.
+    pub fn account_deletion_grace_period_days(&self) -> i64 {
+        self.account_deletion_grace_period_days
+    }
+
+    /// Days an ID photo is kept after its review, for disputes over the result.
+    pub fn verification_picture_retention_days(&self) -> i64 {
+        self.verification_picture_retention_days
+    }
+
+    /// Days a user has to wait before moving to another town again.
+    pub fn town_change_interval_days(&self) -> i64 {
+        self.town_change_interval_days
+    }
+
+    /// Whether verified users have to verify again after moving.
+    pub fn town_change_requires_verification(&self) -> bool {
+        self.town_change_requires_verification
+    }
 }
 
 impl Default for Config {
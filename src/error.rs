@@ -1,14 +1,17 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
 use tracing::error;
 
-use crate::post::{comment::CommentId, PostId};
+use crate::{
+    post::{comment::CommentId, PostId},
+    user::{account::UserId, export::ExportId},
+};
 
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
@@ -24,6 +27,18 @@ pub enum Error {
     Verification,
     #[error("the verification code has been expired")]
     VerificationExpired,
+    #[error("too many failed verification attempts")]
+    TooManyAttempts,
+    #[error("the verification code has been sent recently (retry after {retry_after} seconds)")]
+    ResendTooSoon { retry_after: i64 },
+    #[error("too many verification codes have been sent (retry after {retry_after} seconds)")]
+    SendLimitExceeded { retry_after: i64 },
+    #[error("the town has been changed recently (retry after {retry_after} seconds)")]
+    TownChangeTooSoon { retry_after: i64 },
+    #[error("invalid phone number {0}")]
+    InvalidPhoneNumber(String),
+    #[error("phone number {0} is already in use")]
+    PhoneAlreadyInUse(String),
     #[error("user with phone number {0} not found")]
     UserNotFound(String),
     #[error("user does not exist")]
@@ -36,24 +51,50 @@ pub enum Error {
     TokenNotExists,
     #[error("the token has been expired")]
     TokenExpired,
+    #[error("the refresh token has already been used")]
+    RefreshTokenReused,
+    #[error("the token has been revoked")]
+    RevokedToken,
     #[error("failed to upload file")]
     Upload { path: std::path::PathBuf, source: BoxDynError },
+    #[error("failed to download uploaded file")]
+    Download { path: String, source: BoxDynError },
     #[error("failed to delete uploaded file")]
     DeleteUploaded { path: String, source: BoxDynError },
     #[error("an error occurred while processing the file to be uploaded")]
     FileToStream { path: std::path::PathBuf, source: BoxDynError },
     #[error("an error occurred while processing the file to be uploaded")]
     PersistFile { path: std::path::PathBuf, source: BoxDynError },
+    #[error("the image format is not supported")]
+    UnsupportedImage,
+    #[error("the image cannot be decoded")]
+    InvalidImage(BoxDynError),
     #[error("an error occurred while processing I/O")]
     Io { path: std::path::PathBuf, source: std::io::Error },
     #[error("post id {0} not found")]
     PostNotFound(PostId),
     #[error("comment id {0} not found")]
     CommentNotFound(CommentId),
+    #[error("post id {0} is not a gathering")]
+    NotGathering(PostId),
+    #[error("gathering of post id {0} is full")]
+    GatheringFull(PostId),
+    #[error("gathering of post id {0} has been closed")]
+    GatheringClosed(PostId),
+    #[error("the age is out of the age range of the gathering of post id {0}")]
+    AgeRestricted(PostId),
+    #[error("data export id {0} not found")]
+    ExportNotFound(ExportId),
+    #[error("no pending verification of user {0}")]
+    VerificationNotFound(UserId),
+    #[error("invalid {field}: {reason}")]
+    InvalidField { field: &'static str, reason: String },
     #[error("invalid request")]
     InvalidRequest,
     #[error("the content has blocked")]
     BlockedContent,
+    #[error("permission denied")]
+    PermissionDenied,
     #[error("an error occurred with internal connection")]
     Reqwest {
         #[from]
@@ -77,27 +118,64 @@ impl Error {
             Error::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Verification => StatusCode::UNAUTHORIZED,
             Error::VerificationExpired => StatusCode::UNAUTHORIZED,
+            Error::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            Error::ResendTooSoon { retry_after: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Error::SendLimitExceeded { retry_after: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Error::TownChangeTooSoon { retry_after: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Error::InvalidPhoneNumber(_) => StatusCode::BAD_REQUEST,
+            Error::PhoneAlreadyInUse(_) => StatusCode::CONFLICT,
             Error::UserNotFound(_) => StatusCode::NOT_FOUND,
             Error::DeletedUser => StatusCode::FORBIDDEN,
             Error::Token(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::InvalidToken => StatusCode::BAD_REQUEST,
             Error::TokenNotExists => StatusCode::NOT_FOUND,
             Error::TokenExpired => StatusCode::UNAUTHORIZED,
+            Error::RefreshTokenReused => StatusCode::UNAUTHORIZED,
+            Error::RevokedToken => StatusCode::UNAUTHORIZED,
             Error::Upload { path: _, source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Download { path: _, source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::DeleteUploaded { path: _, source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::FileToStream { path: _, source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::PersistFile { path: _, source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::UnsupportedImage => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::InvalidImage(_) => StatusCode::BAD_REQUEST,
             Error::Io { path: _, source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::PostNotFound(_) => StatusCode::NOT_FOUND,
             Error::CommentNotFound(_) => StatusCode::NOT_FOUND,
+            Error::NotGathering(_) => StatusCode::BAD_REQUEST,
+            Error::GatheringFull(_) => StatusCode::CONFLICT,
+            Error::GatheringClosed(_) => StatusCode::CONFLICT,
+            Error::AgeRestricted(_) => StatusCode::FORBIDDEN,
+            Error::ExportNotFound(_) => StatusCode::NOT_FOUND,
+            Error::VerificationNotFound(_) => StatusCode::NOT_FOUND,
+            Error::InvalidField { field: _, reason: _ } => StatusCode::BAD_REQUEST,
             Error::InvalidRequest => StatusCode::BAD_REQUEST,
             Error::BlockedContent => StatusCode::FORBIDDEN,
+            Error::PermissionDenied => StatusCode::FORBIDDEN,
             Error::Reqwest { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::UrlParse { source: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::MessageSend { code: _, message: _ } => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Unhandled(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// Seconds the client should wait before retrying the request
+    pub(crate) fn retry_after(&self) -> Option<i64> {
+        match self {
+            Error::ResendTooSoon { retry_after } => Some(*retry_after),
+            Error::SendLimitExceeded { retry_after } => Some(*retry_after),
+            Error::TownChangeTooSoon { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// Request field that failed the validation
+    pub(crate) fn field(&self) -> Option<&'static str> {
+        match self {
+            Error::InvalidField { field, reason: _ } => Some(*field),
+            _ => None,
+        }
+    }
 }
 
 impl IntoResponse for Error {
@@ -111,6 +189,9 @@ impl IntoResponse for Error {
             Error::Upload { ref path, ref source } => {
                 error!("failed to upload file {}: {source}", path.to_string_lossy())
             }
+            Error::Download { ref path, ref source } => {
+                error!("failed to download file {path}: {source}")
+            }
             Error::FileToStream { ref path, ref source } => {
                 error!(
                     "failed to create byte stream from file {}: {source}",
@@ -135,9 +216,24 @@ impl IntoResponse for Error {
         #[derive(Serialize)]
         struct ErrorResponse {
             message: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            retry_after: Option<i64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            field: Option<&'static str>,
+        }
+
+        let retry_after = self.retry_after();
+        let mut response = (
+            self.status(),
+            Json(ErrorResponse { message: self.to_string(), retry_after, field: self.field() }),
+        )
+            .into_response();
+
+        if let Some(retry_after) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
         }
 
-        (self.status(), Json(ErrorResponse { message: self.to_string() })).into_response()
+        response
     }
 }
 
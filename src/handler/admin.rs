@@ -0,0 +1,126 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Extension, Json,
+};
+use axum_typed_multipart::TypedMultipart;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    post::{Post, PostId},
+    schema::{
+        PostResultSchema, RoleUpdateSchema, VerificationQueueSchema, VerificationReviewSchema,
+    },
+    user::{
+        account::{User, UserId, VerificationResult},
+        role::{Admin, Moderator, RequireRole, Role},
+        verification::{self, PendingVerification, PICTURE_LINK_LIFETIME},
+    },
+    AppState, Result,
+};
+
+pub(crate) async fn update_user_role(
+    _: RequireRole<Admin>,
+    Path(target_id): Path<UserId>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    TypedMultipart(RoleUpdateSchema { role }): TypedMultipart<RoleUpdateSchema>,
+) -> Result<impl IntoResponse> {
+    let mut target = User::from_id(target_id, &state.database).await?;
+
+    target.update_role(role, &state.database).await?;
+
+    info!("role of user {target_id} has been changed to {role} by admin {}", user.id());
+
+    #[derive(Serialize)]
+    struct RoleUpdateResult {
+        id: UserId,
+        role: Role,
+    }
+    Ok(Json(RoleUpdateResult { id: target_id, role }))
+}
+
+pub(crate) async fn remove_post(
+    _: RequireRole<Moderator>,
+    Path(post_id): Path<PostId>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let post = Post::from_id_unscoped(post_id, &state.database).await?;
+    let author_id = post.author_id();
+
+    post.remove(&state.database, &state.s3).await?;
+
+    info!("post {post_id} has been removed by moderator {}", user.id());
+
+    Ok(Json(PostResultSchema { post_id, author_id }))
+}
+
+pub(crate) async fn get_pending_verifications(
+    _: RequireRole<Admin>,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<VerificationQueueSchema>,
+) -> Result<impl IntoResponse> {
+    let verifications = PendingVerification::get(query.limit(), &state.database).await?;
+
+    Ok(Json(verifications))
+}
+
+pub(crate) async fn get_verification_picture(
+    _: RequireRole<Admin>,
+    Path(target_id): Path<UserId>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let verification = PendingVerification::from_user_id(target_id, &state.database).await?;
+    let url = verification.picture_link(&state.s3).await?;
+
+    info!("verification picture of user {target_id} has been viewed by admin {}", user.id());
+
+    #[derive(Serialize)]
+    struct VerificationPictureResult {
+        url: String,
+        expires_in: u64,
+    }
+    Ok(Json(VerificationPictureResult { url, expires_in: PICTURE_LINK_LIFETIME.as_secs() }))
+}
+
+pub(crate) async fn review_verification(
+    _: RequireRole<Admin>,
+    Path(target_id): Path<UserId>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    TypedMultipart(VerificationReviewSchema { result }): TypedMultipart<VerificationReviewSchema>,
+) -> Result<impl IntoResponse> {
+    let verification = PendingVerification::from_user_id(target_id, &state.database).await?;
+    let phone = verification.phone().clone();
+
+    verification.review(&user, result, &state.database).await?;
+
+    info!(
+        "verification of user {target_id} has been reviewed as {result:?} by admin {}",
+        user.id()
+    );
+
+    // The review is already saved, so a failed notification must not fail the request
+    if let Err(err) = state
+        .config
+        .message_provider()
+        .send_notification(&phone, &verification::review_message(result))
+        .await
+    {
+        warn!("failed to notify user {target_id} of the verification review: {err:?}");
+    }
+
+    #[derive(Serialize)]
+    struct VerificationReviewResult {
+        id: UserId,
+        result: VerificationResult,
+    }
+    Ok(Json(VerificationReviewResult { id: target_id, result }))
+}
@@ -1,7 +1,9 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
+pub(crate) mod admin;
 pub(crate) mod post;
 pub(crate) mod root;
 pub(crate) mod user;
+pub(crate) mod well_known;
 
 pub(crate) use root::root;
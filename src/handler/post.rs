@@ -13,13 +13,19 @@ use serde::Serialize;
 use crate::{
     post::{
         comment::{Comment, CommentId},
+        gathering::Participant,
+        search::{self, SearchHit},
         Post, PostId,
     },
     schema::{
-        CommentCreationSchema, CommentGetResult, PostCreationSchema, PostEditSchema, PostGetResult,
-        PostListSchema, PostResultSchema,
+        CommentCreationSchema, CommentGetResult, ListSchema, ParticipantListItem,
+        ParticipationResult, PostCreationSchema, PostEditSchema, PostGetResult, PostListSchema,
+        PostResultSchema, PostSearchResult, PostSearchSchema, UserLikeListItem,
+    },
+    user::{
+        account::{User, UserId},
+        like::PostLike,
     },
-    user::account::{User, UserId},
     AppState, Error, Result,
 };
 
@@ -47,11 +53,11 @@ pub(crate) async fn edit_post(
     Path(post_id): Path<u64>,
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<User>,
-    TypedMultipart(PostEditSchema { content, images }): TypedMultipart<PostEditSchema>,
+    TypedMultipart(payload): TypedMultipart<PostEditSchema>,
 ) -> Result<impl IntoResponse> {
     let post = Post::from_id(post_id, &user, &state.database).await?;
 
-    post.edit(user.id(), &content, images, &state.database, &state.s3).await?;
+    post.edit(user.id(), payload, &state.database, &state.s3).await?;
 
     Ok(Json(PostResultSchema { post_id, author_id: user.id() }))
 }
@@ -68,6 +74,71 @@ pub(crate) async fn delete_post(
     Ok(Json(PostResultSchema { post_id, author_id: user.id() }))
 }
 
+pub(crate) async fn get_post_likes(
+    Path(post_id): Path<PostId>,
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let post = Post::from_id(post_id, &user, &state.database).await?;
+    let likes =
+        PostLike::from_post_id(post.id(), &user, params.last_id(), params.limit(), &state.database)
+            .await?;
+
+    let likes = likes.iter().map(|like| (like.id(), like.user_id())).collect();
+
+    Ok(Json(UserLikeListItem::from_likes(likes, &state.database).await?))
+}
+
+pub(crate) async fn join_gathering(
+    Path(post_id): Path<PostId>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let post = Post::from_id(post_id, &user, &state.database).await?;
+    Participant::join(&post, &user, &state.database).await?;
+
+    Ok(Json(ParticipationResult {
+        user_id: user.id(),
+        post_id,
+        total_participants: Participant::count(post_id, &state.database).await?,
+    }))
+}
+
+pub(crate) async fn leave_gathering(
+    Path(post_id): Path<PostId>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let post = Post::from_id(post_id, &user, &state.database).await?;
+    Participant::leave(&post, &user, &state.database).await?;
+
+    Ok(Json(ParticipationResult {
+        user_id: user.id(),
+        post_id,
+        total_participants: Participant::count(post_id, &state.database).await?,
+    }))
+}
+
+pub(crate) async fn get_gathering_participants(
+    Path(post_id): Path<PostId>,
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let post = Post::from_id(post_id, &user, &state.database).await?;
+    let participants = Participant::from_post_id(
+        post.id(),
+        &user,
+        params.last_id(),
+        params.limit(),
+        &state.database,
+    )
+    .await?;
+
+    Ok(Json(ParticipantListItem::from_participants(participants, &state.database).await?))
+}
+
 pub(crate) async fn create_post_comment(
     Path(post_id): Path<u64>,
     State(state): State<Arc<AppState>>,
@@ -138,7 +209,28 @@ pub(crate) async fn get_post_list(
     State(state): State<Arc<AppState>>,
     Extension(user): Extension<User>,
 ) -> Result<impl IntoResponse> {
-    let posts = Post::get(&user, params.last_id(), params.limit(), &state.database).await?;
+    let posts = Post::get(&user, &params, &state.database).await?;
 
     Ok(Json(PostGetResult::from_posts(posts, &user, &state.database).await?))
 }
+
+pub(crate) async fn search_posts(
+    Query(params): Query<PostSearchSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let query = search::validate_query(&params.q)?;
+    let hits =
+        SearchHit::search(&user, &query, params.offset(), params.limit(), &state.database).await?;
+
+    let mut result = Vec::with_capacity(hits.len());
+    for hit in hits {
+        result.push(PostSearchResult {
+            post: PostGetResult::from_post(hit.post(), &user, &state.database).await?,
+            score: hit.score(),
+            highlights: hit.highlights(&user, &query, &state.database).await?,
+        });
+    }
+
+    Ok(Json(result))
+}
@@ -1,18 +1,22 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use axum::{
-    extract::{Path, Query, State},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Extension, Json,
 };
 use axum_extra::{
-    headers::{authorization::Bearer, Authorization},
+    headers::{authorization::Bearer, Authorization, UserAgent},
     TypedHeader,
 };
 use axum_typed_multipart::TypedMultipart;
-use chrono::{Datelike, Duration};
+use chrono::Duration;
 use serde::Serialize;
 
 use crate::{
@@ -21,14 +25,25 @@ use crate::{
         Post, PostId,
     },
     schema::{
-        PhoneVerificationSchema, PhoneVerificationSetupSchema, PostGetResult, PostLikeResult,
-        PostListSchema, ProfileBioUpdateSchema, ProfilePictureUpdateSchema, RegistrationSchema,
-        TokenSchema, UserLikeResult, UserVerification,
+        BlockedCommentItem, BlockedPostItem, BlockedUserItem, ListSchema, LogoutSchema,
+        PhoneUpdateSchema, PhoneVerificationSchema, PhoneVerificationSetupSchema,
+        PictureThumbnails, PostGetResult, PostLikeListItem, PostLikeResult, PostListSchema,
+        PrivacySettingsUpdateSchema, ProfileBioUpdateSchema, ProfilePictureUpdateSchema,
+        ProfileUpdateSchema, RegistrationSchema, TokenSchema, TownUpdateSchema, UserLikeListItem,
+        UserLikeResult, UserVerification,
     },
+    town::Town,
     user::{
         account::{User, UserId},
         authentication::PhoneAuthentication,
-        jwt::{authorize_user, Token},
+        block::{CommentBlock, PostBlock, UserBlock},
+        export::{DataExport, ExportId, DOWNLOAD_LINK_LIFETIME},
+        jwt::{authorize_user, Token, TokenType},
+        like::{PostLike, UserLike},
+        privacy::PrivacySettings,
+        session::Session,
+        test_account::TestAccount,
+        verification::{Verification, VerificationId},
         IdVerificationType,
     },
     AppState, Error, Result,
@@ -36,6 +51,7 @@ use crate::{
 
 pub async fn create_user(
     State(state): State<Arc<AppState>>,
+    user_agent: Option<TypedHeader<UserAgent>>,
     TypedMultipart(payload): TypedMultipart<RegistrationSchema>,
 ) -> Result<impl IntoResponse> {
     let phone = payload.phone.clone();
@@ -47,7 +63,14 @@ pub async fn create_user(
 
     PhoneAuthentication::cancel(&phone, &state.database).await?;
 
-    Ok(Json(create_jwt_token_pairs(&user, &state).await?))
+    let mut session = Session::create(
+        &user,
+        user_agent.as_ref().map(|TypedHeader(user_agent)| user_agent.as_str()),
+        &state.database,
+    )
+    .await?;
+
+    Ok(Json(create_jwt_token_pairs(&user, &mut session, &state).await?))
 }
 
 pub(crate) async fn delete_user(
@@ -57,6 +80,7 @@ pub(crate) async fn delete_user(
     let id = user.id();
 
     user.treat_as_deleted(&state.database).await?;
+    Session::revoke_all(id, &state.database).await?;
 
     #[derive(Serialize)]
     struct UserDeletionResult {
@@ -87,33 +111,142 @@ pub(crate) async fn get_user_info(
     Ok(Json(user.to_schema(&state.database).await?))
 }
 
+pub(crate) async fn update_user_info(
+    Extension(mut user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    TypedMultipart(payload): TypedMultipart<ProfileUpdateSchema>,
+) -> Result<impl IntoResponse> {
+    user.update_profile(payload.validate()?, &state.database).await?;
+
+    Ok(Json(user.to_schema(&state.database).await?))
+}
+
+pub(crate) async fn update_town(
+    Extension(mut user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    TypedMultipart(TownUpdateSchema { address }): TypedMultipart<TownUpdateSchema>,
+) -> Result<impl IntoResponse> {
+    let town = user
+        .update_town(
+            &address,
+            state.config.town_change_interval_days(),
+            state.config.town_change_requires_verification(),
+            &state.database,
+        )
+        .await?;
+
+    #[derive(Serialize)]
+    struct TownUpdateResult {
+        id: UserId,
+        town: Town,
+        verified: bool,
+    }
+
+    Ok(Json(TownUpdateResult { id: user.id(), town, verified: user.is_verified() }))
+}
+
+pub(crate) async fn request_data_export(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse> {
+    let export = DataExport::request(&user, state.clone()).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(export)))
+}
+
+pub(crate) async fn get_data_export(
+    Path(export_id): Path<ExportId>,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse> {
+    let export = DataExport::from_id(export_id, &user, &state.database).await?;
+    let url = export.download_link(&state.s3).await?;
+
+    #[derive(Serialize)]
+    struct DataExportResult {
+        #[serde(flatten)]
+        export: DataExport,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expires_in: Option<u64>,
+    }
+
+    Ok(Json(DataExportResult {
+        expires_in: url.as_ref().map(|_| DOWNLOAD_LINK_LIFETIME.as_secs()),
+        url,
+        export,
+    }))
+}
+
 pub(crate) async fn refresh_authorization(
     TypedHeader(Authorization(refresh_token)): TypedHeader<Authorization<Bearer>>,
     State(state): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse> {
     let refresh_token = refresh_token.token();
-    let token = authorize_user(Some(refresh_token), state.config.public_key()).await?;
+    let token = authorize_user(Some(refresh_token), state.config.key_set()).await?;
+
+    token.expect_type(TokenType::Refresh)?;
+
+    let mut session =
+        Session::from_id(token.session_id().ok_or(Error::InvalidToken)?, &state.database).await?;
+
+    session.verify_refresh_token(&token, &state.database).await?;
+
     let user = User::from_id(token.user_id(), &state.database).await?;
 
-    user.verify_refresh_token(refresh_token)?;
+    Ok(Json(create_jwt_token_pairs(&user, &mut session, &state).await?))
+}
 
-    Ok(Json(create_jwt_token_pairs(&user, &state).await?))
+pub(crate) async fn logout(
+    Query(params): Query<LogoutSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Extension(token): Extension<Token>,
+) -> Result<impl IntoResponse> {
+    if params.all() {
+        Session::revoke_all(user.id(), &state.database).await?;
+    } else if let Some(session_id) = token.session_id() {
+        Session::from_id(session_id, &state.database).await?.revoke(&state.database).await?;
+    }
+
+    token.revoke(&state.database).await?;
+
+    #[derive(Serialize)]
+    struct LogoutResult {
+        id: UserId,
+    }
+    Ok(Json(LogoutResult { id: user.id() }))
 }
 
 pub async fn setup_phone_authorization(
     State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     TypedMultipart(PhoneVerificationSetupSchema { phone }): TypedMultipart<
         PhoneVerificationSetupSchema,
     >,
 ) -> Result<impl IntoResponse> {
-    let user =
-        User::from_phone(&phone, &state.database).await.map(Some).or_else(|err| match err {
-            Error::UserNotFound(_) => Ok(None),
-            _ => Err(err),
-        })?;
-    let bypass =
-        matches!(user, Some(user) if user.created_at().year() == 1970) || phone == "20973917696";
-    let result = PhoneAuthentication::send(&phone, !bypass, &state.database).await?;
+    let test_account = match state.config.test_accounts_enabled() {
+        true => TestAccount::from_phone(&phone, &state.database).await?,
+        false => None,
+    };
+    let result = match &test_account {
+        Some(test_account) => {
+            test_account.audit("requested a verification code");
+
+            PhoneAuthentication::send_test(test_account, &state.database).await?
+        }
+        None => {
+            PhoneAuthentication::send(
+                &phone,
+                client_ip(&headers, connect_info, state.config.trusted_proxy_hops()),
+                state.config.message_provider(),
+                &state.database,
+            )
+            .await?
+        }
+    };
 
     #[derive(Serialize)]
     struct PhoneAuthenticationSetupResult {
@@ -121,29 +254,101 @@ pub async fn setup_phone_authorization(
         code: Option<String>,
     }
     Ok(Json(PhoneAuthenticationSetupResult {
-        code: {
-            if !bypass {
-                None
-            } else {
-                Some(result.code().to_string())
-            }
-        },
+        code: test_account.map(|_| result.code().to_string()),
     }))
 }
 
+/// Address of the client, taken from the `X-Forwarded-For` entry appended by
+/// the outermost trusted proxy. Entries left of it can be forged by the client.
+fn client_ip(
+    headers: &HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    trusted_proxy_hops: usize,
+) -> Option<IpAddr> {
+    let peer_ip = connect_info.map(|ConnectInfo(address)| address.ip());
+
+    if trusted_proxy_hops == 0 {
+        return peer_ip;
+    }
+
+    // Proxies may append to the header or add another one, in order
+    let forwarded_for: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    match forwarded_for.len().checked_sub(trusted_proxy_hops) {
+        Some(index) => forwarded_for[index].parse().ok().or(peer_ip),
+        None => peer_ip,
+    }
+}
+
 pub async fn authorize_phone(
     State(state): State<Arc<AppState>>,
+    user_agent: Option<TypedHeader<UserAgent>>,
     TypedMultipart(PhoneVerificationSchema { phone, code }): TypedMultipart<
         PhoneVerificationSchema,
     >,
 ) -> Result<impl IntoResponse> {
     PhoneAuthentication::authorize(&phone, &code, &state.database).await?;
 
-    let user = User::from_phone(&phone, &state.database).await?;
+    // Logging in again restores an account that is waiting to be purged
+    let user = match User::from_phone(&phone, &state.database).await {
+        Err(Error::DeletedUser) => {
+            User::restore(
+                &phone,
+                state.config.account_deletion_grace_period_days(),
+                &state.database,
+            )
+            .await?
+        }
+        result => result?,
+    };
+
+    if state.config.test_accounts_enabled() {
+        if let Some(test_account) = TestAccount::from_phone(&phone, &state.database).await? {
+            test_account.audit(&format!("logged in as user {}", user.id()));
+        }
+    }
+
+    PhoneAuthentication::cancel(&phone, &state.database).await?;
+
+    let mut session = Session::create(
+        &user,
+        user_agent.as_ref().map(|TypedHeader(user_agent)| user_agent.as_str()),
+        &state.database,
+    )
+    .await?;
+
+    Ok(Json(create_jwt_token_pairs(&user, &mut session, &state).await?))
+}
+
+pub(crate) async fn update_phone(
+    State(state): State<Arc<AppState>>,
+    Extension(mut user): Extension<User>,
+    user_agent: Option<TypedHeader<UserAgent>>,
+    TypedMultipart(PhoneUpdateSchema { phone, code }): TypedMultipart<PhoneUpdateSchema>,
+) -> Result<impl IntoResponse> {
+    PhoneAuthentication::authorize(&phone, &code, &state.database).await?;
+
+    user.update_phone(&phone, &state.database).await?;
 
     PhoneAuthentication::cancel(&phone, &state.database).await?;
 
-    Ok(Json(create_jwt_token_pairs(&user, &state).await?))
+    // Log out every device since the account has moved to the new number
+    Session::revoke_all(user.id(), &state.database).await?;
+
+    let mut session = Session::create(
+        &user,
+        user_agent.as_ref().map(|TypedHeader(user_agent)| user_agent.as_str()),
+        &state.database,
+    )
+    .await?;
+
+    Ok(Json(create_jwt_token_pairs(&user, &mut session, &state).await?))
 }
 
 pub(crate) async fn update_profile_picture(
@@ -159,9 +364,53 @@ pub(crate) async fn update_profile_picture(
     struct PictureUpdateResult {
         id: UserId,
         picture: String,
+        picture_thumbnails: PictureThumbnails,
+    }
+
+    Ok(Json(PictureUpdateResult {
+        id: user.id(),
+        picture: picture_url,
+        picture_thumbnails: user.picture_thumbnails(),
+    }))
+}
+
+pub(crate) async fn reset_profile_picture(
+    Extension(mut user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse> {
+    let picture_url = user.reset_picture(&state.s3, &state.database).await?;
+
+    #[derive(Serialize)]
+    struct PictureResetResult {
+        id: UserId,
+        picture: String,
+        picture_thumbnails: PictureThumbnails,
     }
 
-    Ok(Json(PictureUpdateResult { id: user.id(), picture: picture_url }))
+    Ok(Json(PictureResetResult {
+        id: user.id(),
+        picture: picture_url,
+        picture_thumbnails: user.picture_thumbnails(),
+    }))
+}
+
+pub(crate) async fn get_privacy_settings(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse> {
+    Ok(Json(PrivacySettings::from_user_id(user.id(), &state.database).await?))
+}
+
+pub(crate) async fn update_privacy_settings(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    TypedMultipart(payload): TypedMultipart<PrivacySettingsUpdateSchema>,
+) -> Result<impl IntoResponse> {
+    let mut privacy = PrivacySettings::from_user_id(user.id(), &state.database).await?;
+
+    privacy.update(payload, &state.database).await?;
+
+    Ok(Json(privacy))
 }
 
 pub(crate) async fn update_profile_bio(
@@ -222,6 +471,42 @@ pub(crate) async fn cancel_like_post(
     Ok(Json(PostLikeResult { user_id: user.id(), post_id }))
 }
 
+pub(crate) async fn get_received_likes(
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let likes =
+        UserLike::received(&user, params.last_id(), params.limit(), &state.database).await?;
+
+    let likes = likes.iter().map(|like| (like.id(), like.user_id())).collect();
+
+    Ok(Json(UserLikeListItem::from_likes(likes, &state.database).await?))
+}
+
+pub(crate) async fn get_given_likes(
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let likes = UserLike::given(&user, params.last_id(), params.limit(), &state.database).await?;
+
+    let likes = likes.iter().map(|like| (like.id(), like.user_id())).collect();
+
+    Ok(Json(UserLikeListItem::from_likes(likes, &state.database).await?))
+}
+
+pub(crate) async fn get_liked_posts(
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let likes =
+        PostLike::from_user(&user, params.last_id(), params.limit(), &state.database).await?;
+
+    Ok(Json(PostLikeListItem::from_likes(likes, &user, &state.database).await?))
+}
+
 pub(crate) async fn get_my_posts(
     Query(params): Query<PostListSchema>,
     State(state): State<Arc<AppState>>,
@@ -240,11 +525,45 @@ pub(crate) async fn get_user_posts(
 ) -> Result<impl IntoResponse> {
     let target_user = User::from_id(target_id, &state.database).await?;
     let posts =
-        Post::from_user(&target_user, params.last_id(), params.limit(), &state.database).await?;
+        Post::from_author(&target_user, &user, params.last_id(), params.limit(), &state.database)
+            .await?;
 
     Ok(Json(PostGetResult::from_posts(posts, &user, &state.database).await?))
 }
 
+pub(crate) async fn get_blocked_users(
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let blocks =
+        UserBlock::from_user(&user, params.last_id(), params.limit(), &state.database).await?;
+
+    Ok(Json(BlockedUserItem::from_blocks(blocks, &state.database).await?))
+}
+
+pub(crate) async fn get_blocked_posts(
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let blocks =
+        PostBlock::from_user(&user, params.last_id(), params.limit(), &state.database).await?;
+
+    Ok(Json(BlockedPostItem::from_blocks(blocks, &state.database).await?))
+}
+
+pub(crate) async fn get_blocked_comments(
+    Query(params): Query<ListSchema>,
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let blocks =
+        CommentBlock::from_user(&user, params.last_id(), params.limit(), &state.database).await?;
+
+    Ok(Json(BlockedCommentItem::from_blocks(blocks, &state.database).await?))
+}
+
 #[derive(Serialize)]
 struct UserBlockResult {
     id: UserId,
@@ -345,44 +664,112 @@ pub(crate) async fn unblock_post_comment(
 
 pub(crate) async fn update_verification(
     State(state): State<Arc<AppState>>,
-    Extension(mut user): Extension<User>,
+    Extension(user): Extension<User>,
     TypedMultipart(UserVerification { verification_type, verification_picture }): TypedMultipart<
         UserVerification,
     >,
 ) -> Result<impl IntoResponse> {
-    let picture_url = user
-        .update_verification(verification_type, verification_picture, &state.database, &state.s3)
-        .await?;
+    let verification = Verification::submit(
+        &user,
+        verification_type,
+        verification_picture,
+        &state.database,
+        &state.s3,
+    )
+    .await?;
 
     #[derive(Serialize)]
     struct VerificationUpdateResult {
         id: UserId,
+        verification_id: VerificationId,
         verification_type: IdVerificationType,
-        verification_picture_url: String,
     }
 
     Ok(Json(VerificationUpdateResult {
         id: user.id(),
-        verification_type,
-        verification_picture_url: picture_url,
+        verification_id: verification.id(),
+        verification_type: verification.verification_type(),
     }))
 }
 
-async fn create_jwt_token_pairs(user: &User, state: &Arc<AppState>) -> Result<TokenSchema> {
+pub(crate) async fn get_verification_history(
+    State(state): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse> {
+    let history = Verification::history(&user, &state.database).await?;
+
+    Ok(Json(history))
+}
+
+async fn create_jwt_token_pairs(
+    user: &User,
+    session: &mut Session,
+    state: &Arc<AppState>,
+) -> Result<TokenSchema> {
     let access_token = Token::new(
-        state.config.private_key(),
+        state.config.key_set(),
+        TokenType::Access,
         Duration::seconds(state.config.access_token_max_age()),
-        user.id(),
-    )
-    .map(|token| token.encoded_token().to_string())?;
+        user,
+        session.id(),
+    )?;
     let refresh_token = Token::new(
-        state.config.private_key(),
+        state.config.key_set(),
+        TokenType::Refresh,
         Duration::seconds(state.config.refresh_token_max_age()),
-        user.id(),
-    )
-    .map(|token| token.encoded_token().to_string())?;
+        user,
+        session.id(),
+    )?;
+
+    session.rotate(&access_token, &refresh_token, &state.database).await?;
+
+    Ok(TokenSchema {
+        user_id: user.id(),
+        access_token: access_token.encoded_token().to_string(),
+        refresh_token: refresh_token.encoded_token().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
 
-    user.update_refresh_token(&refresh_token, &state.database).await?;
+    const PEER: &str = "10.0.0.1:443";
 
-    Ok(TokenSchema { user_id: user.id(), access_token, refresh_token })
+    fn headers(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn client_ip_of(values: &[&str], trusted_proxy_hops: usize) -> Option<String> {
+        let peer = ConnectInfo(PEER.parse::<SocketAddr>().unwrap());
+
+        client_ip(&headers(values), Some(peer), trusted_proxy_hops).map(|ip| ip.to_string())
+    }
+
+    #[test]
+    fn peer_address_is_used_without_trusted_proxies() {
+        assert_eq!(client_ip_of(&["1.1.1.1"], 0).as_deref(), Some("10.0.0.1"));
+        assert_eq!(client_ip(&headers(&["1.1.1.1"]), None, 0), None);
+    }
+
+    #[test]
+    fn entry_appended_by_the_outermost_trusted_proxy_is_used() {
+        assert_eq!(client_ip_of(&["1.1.1.1"], 1).as_deref(), Some("1.1.1.1"));
+        assert_eq!(client_ip_of(&["6.6.6.6, 1.1.1.1"], 1).as_deref(), Some("1.1.1.1"));
+        assert_eq!(client_ip_of(&["6.6.6.6, 1.1.1.1", "2.2.2.2"], 2).as_deref(), Some("1.1.1.1"));
+        assert_eq!(client_ip_of(&["::1,2001:db8::1"], 1).as_deref(), Some("2001:db8::1"));
+    }
+
+    #[test]
+    fn peer_address_is_used_without_a_usable_entry() {
+        assert_eq!(client_ip_of(&[], 1).as_deref(), Some("10.0.0.1"));
+        assert_eq!(client_ip_of(&["1.1.1.1"], 2).as_deref(), Some("10.0.0.1"));
+        assert_eq!(client_ip_of(&["unknown"], 1).as_deref(), Some("10.0.0.1"));
+    }
 }
@@ -0,0 +1,11 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::AppState;
+
+pub(crate) async fn jwks(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.config.key_set().jwks())
+}
@@ -3,9 +3,11 @@
 pub mod config;
 pub mod env;
 pub mod error;
+pub mod message;
 
 mod aws;
 mod handler;
+mod media;
 mod post;
 mod schema;
 mod town;
@@ -14,6 +16,7 @@ mod user;
 use std::sync::Arc;
 
 pub use error::{Error, Result};
+pub use user::phone::PhoneNumber;
 
 use axum::{
     extract::DefaultBodyLimit,
@@ -36,20 +39,51 @@ pub async fn app(config: Config, database: &sqlx::Pool<MySql>) -> axum::Router {
         s3: aws::S3Client::from_env().await,
     });
 
+    user::deletion::AccountDeletion::spawn_purge_job(state.clone());
+    user::verification::Verification::spawn_retention_job(state.clone());
+    user::export::DataExport::spawn_cleanup_job(state.clone());
+    user::jwt::Token::spawn_pruning_job(state.clone());
+    post::gathering::GatheringSchedule::spawn_closing_job(state.clone());
+
     let auth_layer =
         middleware::from_fn_with_state(state.clone(), user::jwt::authorize_user_middleware);
 
-    let root_routers = axum::Router::new().route("/", get(handler::root));
+    let root_routers = axum::Router::new()
+        .route("/", get(handler::root))
+        .route("/.well-known/jwks.json", get(handler::well_known::jwks));
     let user_routers = axum::Router::new()
         .route("/user", post(handler::user::create_user))
         .route("/user/:id", get(handler::user::get_other_user_info).route_layer(auth_layer.clone()))
         .route("/user/:id", delete(handler::user::delete_user).route_layer(auth_layer.clone()))
         .route("/user/:id/post", get(handler::user::get_user_posts).route_layer(auth_layer.clone()))
         .route("/user/me", get(handler::user::get_user_info).route_layer(auth_layer.clone()))
+        .route("/user/me", patch(handler::user::update_user_info).route_layer(auth_layer.clone()))
+        .route("/user/me/town", patch(handler::user::update_town).route_layer(auth_layer.clone()))
+        .route(
+            "/user/me/export",
+            post(handler::user::request_data_export).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/me/export/:id",
+            get(handler::user::get_data_export).route_layer(auth_layer.clone()),
+        )
         .route(
             "/user/me/picture",
             patch(handler::user::update_profile_picture).route_layer(auth_layer.clone()),
         )
+        .route(
+            "/user/me/picture",
+            delete(handler::user::reset_profile_picture).route_layer(auth_layer.clone()),
+        )
+        .route("/user/me/phone", patch(handler::user::update_phone).route_layer(auth_layer.clone()))
+        .route(
+            "/user/me/privacy",
+            get(handler::user::get_privacy_settings).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/me/privacy",
+            patch(handler::user::update_privacy_settings).route_layer(auth_layer.clone()),
+        )
         .route(
             "/user/me/bio",
             patch(handler::user::update_profile_bio).route_layer(auth_layer.clone()),
@@ -70,6 +104,18 @@ pub async fn app(config: Config, database: &sqlx::Pool<MySql>) -> axum::Router {
             "/user/me/like/post/:id",
             delete(handler::user::cancel_like_post).route_layer(auth_layer.clone()),
         )
+        .route(
+            "/user/me/block/user",
+            get(handler::user::get_blocked_users).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/me/block/post",
+            get(handler::user::get_blocked_posts).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/me/block/comment",
+            get(handler::user::get_blocked_comments).route_layer(auth_layer.clone()),
+        )
         .route(
             "/user/me/block/user/:id",
             post(handler::user::block_user).route_layer(auth_layer.clone()),
@@ -94,28 +140,63 @@ pub async fn app(config: Config, database: &sqlx::Pool<MySql>) -> axum::Router {
             "/user/me/block/post/:id/comment/:id",
             delete(handler::user::unblock_post_comment).route_layer(auth_layer.clone()),
         )
+        .route(
+            "/user/me/likes/received",
+            get(handler::user::get_received_likes).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/me/likes/given",
+            get(handler::user::get_given_likes).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/me/liked-posts",
+            get(handler::user::get_liked_posts).route_layer(auth_layer.clone()),
+        )
         .route("/user/me/post", get(handler::user::get_my_posts).route_layer(auth_layer.clone()))
         .route("/user/authentication", patch(handler::user::refresh_authorization))
+        .route(
+            "/user/authentication",
+            delete(handler::user::logout).route_layer(auth_layer.clone()),
+        )
         .route("/user/authentication/phone", post(handler::user::setup_phone_authorization))
         .route("/user/authentication/phone", put(handler::user::authorize_phone))
-        .route("/user/verification", patch(handler::user::update_verification))
-        // .route("/user/verification", patch(handler::user::update_verification))
-        ;
+        .route(
+            "/user/verification",
+            patch(handler::user::update_verification).route_layer(auth_layer.clone()),
+        )
+        .route(
+            "/user/verification",
+            get(handler::user::get_verification_history).route_layer(auth_layer.clone()),
+        );
     let post_routers = axum::Router::new()
         .route("/post", post(handler::post::create_post))
         .route("/post", get(handler::post::get_post_list))
+        .route("/post/search", get(handler::post::search_posts))
         .route("/post/:id", get(handler::post::get_post))
         .route("/post/:id", patch(handler::post::edit_post))
         .route("/post/:id", delete(handler::post::delete_post))
+        .route("/post/:id/likes", get(handler::post::get_post_likes))
+        .route("/post/:id/participant", post(handler::post::join_gathering))
+        .route("/post/:id/participant", delete(handler::post::leave_gathering))
+        .route("/post/:id/participant", get(handler::post::get_gathering_participants))
         .route("/post/:id/comment", post(handler::post::create_post_comment))
         .route("/post/:id/comment", get(handler::post::get_post_comments))
         .route("/post/:id/comment/:id", delete(handler::post::delete_post_comment))
         .route_layer(auth_layer.clone());
 
+    let admin_routers = axum::Router::new()
+        .route("/admin/user/:id/role", patch(handler::admin::update_user_role))
+        .route("/admin/post/:id", delete(handler::admin::remove_post))
+        .route("/admin/verification", get(handler::admin::get_pending_verifications))
+        .route("/admin/verification/:id", patch(handler::admin::review_verification))
+        .route("/admin/verification/:id/picture", get(handler::admin::get_verification_picture))
+        .route_layer(auth_layer.clone());
+
     axum::Router::new()
         .merge(root_routers)
         .merge(user_routers)
         .merge(post_routers)
+        .merge(admin_routers)
         .layer(DefaultBodyLimit::max(1024 * 1024 * 50)) // 10 MB
         .with_state(state)
 }
@@ -1,5 +1,7 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
+use std::net::SocketAddr;
+
 use dotenvy::dotenv;
 use downtown::{config::Config, env::get_env_or_panic};
 use sqlx::mysql::MySqlPoolOptions;
@@ -44,7 +46,7 @@ async fn main() {
 
     print_server_started(&address);
 
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
 
 fn print_server_started(address: &str) {
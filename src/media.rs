@@ -0,0 +1,195 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::{io::Cursor, path::Path};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat};
+use tempfile::NamedTempFile;
+
+use crate::{Error, Result};
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Sizes every uploaded image is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageVariant {
+    Full,
+    Medium,
+    Small,
+}
+
+impl ImageVariant {
+    const ALL: [ImageVariant; 3] = [ImageVariant::Full, ImageVariant::Medium, ImageVariant::Small];
+
+    /// Longest edge in pixels. Smaller images are never upscaled.
+    fn max_size(self) -> u32 {
+        match self {
+            ImageVariant::Full => 2048,
+            ImageVariant::Medium => 480,
+            ImageVariant::Small => 160,
+        }
+    }
+
+    /// Appended to the storage key of the full-size image.
+    pub(crate) fn key_suffix(self) -> &'static str {
+        match self {
+            ImageVariant::Full => "",
+            ImageVariant::Medium => "_medium",
+            ImageVariant::Small => "_small",
+        }
+    }
+}
+
+/// Format of an uploaded image, detected from its content instead of the file
+/// name or the content type sent by the client.
+#[derive(Debug, Clone, Copy)]
+enum SourceFormat {
+    Jpeg,
+    Png,
+    WebP,
+    Heic,
+}
+
+impl SourceFormat {
+    fn detect(bytes: &[u8]) -> Result<Self> {
+        match image::guess_format(bytes) {
+            Ok(ImageFormat::Jpeg) => Ok(SourceFormat::Jpeg),
+            Ok(ImageFormat::Png) => Ok(SourceFormat::Png),
+            Ok(ImageFormat::WebP) => Ok(SourceFormat::WebP),
+            _ if is_heic(bytes) => Ok(SourceFormat::Heic),
+            _ => Err(Error::UnsupportedImage),
+        }
+    }
+}
+
+/// Checks the brand of the `ftyp` box every HEIF file starts with.
+fn is_heic(bytes: &[u8]) -> bool {
+    const BRANDS: [&[u8]; 6] = [b"heic", b"heix", b"heim", b"heis", b"hevc", b"mif1"];
+
+    bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && BRANDS.contains(&&bytes[8..12])
+}
+
+/// An uploaded image re-encoded as JPEG in every `ImageVariant`.
+///
+/// The orientation recorded in EXIF is applied to the pixels, and no metadata
+/// is carried over to the encoded files, so the location and the device the
+/// picture was taken with are never published.
+pub(crate) struct ProcessedImage {
+    variants: Vec<(ImageVariant, NamedTempFile)>,
+}
+
+impl ProcessedImage {
+    pub(crate) async fn from_file(path: &Path) -> Result<Self> {
+        let path = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || {
+            let bytes = std::fs::read(&path).map_err(|err| Error::Io { path, source: err })?;
+
+            Self::from_bytes(&bytes)
+        })
+        .await
+        .map_err(|err| Error::Unhandled(err.into()))?
+    }
+
+    pub(crate) fn path(&self, variant: ImageVariant) -> &Path {
+        self.variants
+            .iter()
+            .find(|(processed, _)| *processed == variant)
+            .map(|(_, file)| file.path())
+            .expect("every variant is processed")
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let image = match SourceFormat::detect(bytes)? {
+            SourceFormat::Jpeg => decode(bytes, ImageFormat::Jpeg)?,
+            SourceFormat::Png => decode(bytes, ImageFormat::Png)?,
+            SourceFormat::WebP => decode(bytes, ImageFormat::WebP)?,
+            SourceFormat::Heic => decode_heic(bytes)?,
+        };
+        // JPEG has no alpha channel
+        let image = DynamicImage::ImageRgb8(image.to_rgb8());
+
+        let mut variants = Vec::with_capacity(ImageVariant::ALL.len());
+
+        for variant in ImageVariant::ALL {
+            let max_size = variant.max_size();
+            let resized = match image.width() > max_size || image.height() > max_size {
+                true => image.resize(max_size, max_size, FilterType::Lanczos3),
+                false => image.clone(),
+            };
+
+            let mut file = NamedTempFile::new()
+                .map_err(|err| Error::Io { path: std::env::temp_dir(), source: err })?;
+            JpegEncoder::new_with_quality(&mut file, JPEG_QUALITY)
+                .encode_image(&resized)
+                .map_err(|err| Error::Unhandled(err.into()))?;
+
+            variants.push((variant, file));
+        }
+
+        Ok(Self { variants })
+    }
+}
+
+fn decode(bytes: &[u8], format: ImageFormat) -> Result<DynamicImage> {
+    let image = image::load_from_memory_with_format(bytes, format)
+        .map_err(|err| Error::InvalidImage(err.into()))?;
+
+    Ok(apply_orientation(image, read_orientation(bytes)))
+}
+
+/// Reads the EXIF orientation, which is 1 (no transformation) if missing.
+fn read_orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Decodes HEIC with libheif, which also applies the orientation of the image.
+#[cfg(feature = "heic")]
+fn decode_heic(bytes: &[u8]) -> Result<DynamicImage> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let invalid = |err: libheif_rs::HeifError| Error::InvalidImage(err.into());
+
+    let context = HeifContext::read_from_bytes(bytes).map_err(invalid)?;
+    let handle = context.primary_image_handle().map_err(invalid)?;
+    let decoded =
+        LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None).map_err(invalid)?;
+    let plane = decoded.planes().interleaved.ok_or(Error::UnsupportedImage)?;
+
+    // Rows of the plane may be padded, so copy them without the padding
+    let row_length = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_length * plane.height as usize);
+
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_length]);
+    }
+
+    image::RgbImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or(Error::UnsupportedImage)
+}
+
+/// HEIC needs libheif, so it is rejected unless the `heic` feature is enabled.
+#[cfg(not(feature = "heic"))]
+fn decode_heic(_bytes: &[u8]) -> Result<DynamicImage> {
+    Err(Error::UnsupportedImage)
+}
@@ -0,0 +1,227 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use axum::async_trait;
+use once_cell::sync;
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::{env::get_env_or_panic, user::phone::PhoneNumber, Error, Result};
+
+use super::MessageProvider;
+
+static ALIGO_KAKAO_HOST: sync::Lazy<Url> =
+    sync::Lazy::new(|| Url::parse("https://kakaoapi.aligo.in").unwrap());
+static ALIGO_SMS_HOST: sync::Lazy<Url> =
+    sync::Lazy::new(|| Url::parse("https://apis.aligo.in").unwrap());
+
+const ALIGO_TOKEN_CREATE_PATH: &str = "akv10/token/create/";
+const ALIGO_ALIMTALK_SEND_PATH: &str = "akv10/alimtalk/send/";
+const ALIGO_SMS_SEND_PATH: &str = "send/";
+
+const ALIGO_TOKEN_LIFETIME_SEC: i32 = 30;
+
+const ALIGO_MESSAGE_SUBJECT: &str = "이프 휴대폰 인증";
+const ALIGO_MESSAGE_PREFIX: &str = "이프 회원가입을 위해 인증번호 [";
+const ALIGO_MESSAGE_SUFFIX: &str = "]를 입력해주세요.";
+
+/// Account data shared by every Aligo API.
+struct AligoAccount {
+    api_key: String,
+    user_id: String,
+    sender_phone: String,
+    test_mode: bool,
+}
+
+impl AligoAccount {
+    fn from_env() -> Self {
+        Self {
+            api_key: get_env_or_panic("ALIGO_API_KEY"),
+            user_id: get_env_or_panic("ALIGO_USER_ID"),
+            sender_phone: get_env_or_panic("ALIGO_SENDER_PHONE"),
+            test_mode: std::env::var("ALIGO_TEST_MODE").is_ok_and(|value| value == "true"),
+        }
+    }
+
+    async fn send_sms(&self, phone: &PhoneNumber, message: &str) -> Result<()> {
+        let body = [
+            ("key", self.api_key.as_str()),
+            ("user_id", &self.user_id),
+            ("sender", &self.sender_phone),
+            ("receiver", &phone.to_national()),
+            ("msg", message),
+            ("testmode_yn", if self.test_mode { "Y" } else { "N" }),
+        ];
+
+        reqwest::Client::new()
+            .post(ALIGO_SMS_HOST.join(ALIGO_SMS_SEND_PATH)?)
+            .form(&body)
+            .send()
+            .await?
+            .json::<AligoSmsSendResult>()
+            .await
+            .map_err(Error::from)
+            .and_then(|result| match result.code() {
+                code if code > 0 => Ok(()),
+                code => Err(Error::MessageSend { code, message: result.message }),
+            })
+    }
+}
+
+/// Sends messages through the KakaoTalk Alimtalk API of Aligo.
+pub struct AligoAlimtalkProvider {
+    account: AligoAccount,
+    sender_key: String,
+    template_code: String,
+}
+
+#[derive(Deserialize)]
+struct AligoTokenCreationResult {
+    code: i32,
+    #[allow(dead_code)]
+    message: String,
+    token: String,
+    /// URL encoded token
+    #[allow(dead_code)]
+    urlencode: String,
+}
+
+#[derive(Deserialize)]
+struct AligoAlimtalkSendResult {
+    code: i32,
+    #[allow(dead_code)]
+    message: String,
+    #[allow(dead_code)]
+    info: Option<AligoAlimtalkSendInfo>,
+}
+
+#[derive(Deserialize)]
+struct AligoAlimtalkSendInfo {
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    send_type: String,
+    #[allow(dead_code)]
+    mid: Option<i64>,
+    #[allow(dead_code)]
+    current: String,
+    #[allow(dead_code)]
+    unit: f64,
+    #[allow(dead_code)]
+    total: f64,
+    #[allow(dead_code)]
+    scnt: Option<i64>,
+    #[allow(dead_code)]
+    fcnt: Option<i64>,
+}
+
+impl AligoAlimtalkProvider {
+    pub fn from_env() -> Self {
+        Self {
+            account: AligoAccount::from_env(),
+            sender_key: get_env_or_panic("ALIGO_SENDER_KEY"),
+            template_code: get_env_or_panic("ALIGO_TEMPLATE_CODE"),
+        }
+    }
+
+    async fn create_token(&self) -> Result<String> {
+        let body = [("apikey", &self.account.api_key), ("userid", &self.account.user_id)];
+
+        reqwest::Client::new()
+            .post(
+                ALIGO_KAKAO_HOST
+                    .join(ALIGO_TOKEN_CREATE_PATH)?
+                    .join(&format!("{}/", ALIGO_TOKEN_LIFETIME_SEC))?
+                    .join("s/")?,
+            )
+            .form(&body)
+            .send()
+            .await?
+            .json::<AligoTokenCreationResult>()
+            .await
+            .map_err(Into::into)
+            .and_then(|result| match result.code {
+                0 => Ok(result),
+                _ => Err(Error::MessageSend { code: result.code, message: result.message }),
+            })
+            .map(|result| result.token)
+    }
+}
+
+#[async_trait]
+impl MessageProvider for AligoAlimtalkProvider {
+    async fn send_verification_code(&self, phone: &PhoneNumber, code: &str) -> Result<()> {
+        let token = self.create_token().await?;
+        let body = [
+            ("apikey", self.account.api_key.as_str()),
+            ("userid", &self.account.user_id),
+            ("token", &token),
+            ("senderkey", &self.sender_key),
+            ("tpl_code", &self.template_code),
+            ("sender", &self.account.sender_phone),
+            ("receiver_1", &phone.to_national()),
+            ("subject_1", ALIGO_MESSAGE_SUBJECT),
+            ("message_1", &format!("{ALIGO_MESSAGE_PREFIX}{code}{ALIGO_MESSAGE_SUFFIX}")),
+            ("failover", "N"),
+            ("testMode", if self.account.test_mode { "Y" } else { "N" }),
+        ];
+
+        reqwest::Client::new()
+            .post(ALIGO_KAKAO_HOST.join(ALIGO_ALIMTALK_SEND_PATH)?)
+            .form(&body)
+            .send()
+            .await?
+            .json::<AligoAlimtalkSendResult>()
+            .await
+            .map_err(Error::from)
+            .and_then(|result| match result.code {
+                0 => Ok(()),
+                _ => Err(Error::MessageSend { code: result.code, message: result.message }),
+            })
+    }
+
+    /// Alimtalk only delivers messages matching a registered template, so
+    /// notifications are sent as SMS.
+    async fn send_notification(&self, phone: &PhoneNumber, message: &str) -> Result<()> {
+        self.account.send_sms(phone, message).await
+    }
+}
+
+/// Sends plain SMS messages through the SMS API of Aligo.
+pub struct AligoSmsProvider {
+    account: AligoAccount,
+}
+
+#[derive(Deserialize)]
+struct AligoSmsSendResult {
+    /// Positive on success; the API returns this either as a number or a string
+    result_code: serde_json::Value,
+    message: String,
+}
+
+impl AligoSmsSendResult {
+    fn code(&self) -> i32 {
+        match &self.result_code {
+            serde_json::Value::Number(code) => code.as_i64().unwrap_or(-1) as i32,
+            serde_json::Value::String(code) => code.parse().unwrap_or(-1),
+            _ => -1,
+        }
+    }
+}
+
+impl AligoSmsProvider {
+    pub fn from_env() -> Self {
+        Self { account: AligoAccount::from_env() }
+    }
+}
+
+#[async_trait]
+impl MessageProvider for AligoSmsProvider {
+    async fn send_verification_code(&self, phone: &PhoneNumber, code: &str) -> Result<()> {
+        self.account
+            .send_sms(phone, &format!("{ALIGO_MESSAGE_PREFIX}{code}{ALIGO_MESSAGE_SUFFIX}"))
+            .await
+    }
+
+    async fn send_notification(&self, phone: &PhoneNumber, message: &str) -> Result<()> {
+        self.account.send_sms(phone, message).await
+    }
+}
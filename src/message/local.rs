@@ -0,0 +1,54 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use axum::async_trait;
+use tracing::info;
+
+use crate::{user::phone::PhoneNumber, Result};
+
+use super::MessageProvider;
+
+/// Keeps messages in memory instead of delivering them.
+///
+/// Used for development and tests, where the sent codes can be read back.
+#[derive(Default)]
+pub struct LocalMessageProvider {
+    codes: Mutex<HashMap<String, String>>,
+    notifications: Mutex<HashMap<String, String>>,
+}
+
+impl LocalMessageProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the last code sent to `phone`, given in the E.164 format.
+    pub fn last_code(&self, phone: &str) -> Option<String> {
+        self.codes.lock().unwrap().get(phone).cloned()
+    }
+
+    /// Returns the last notification sent to `phone`, given in the E.164 format.
+    pub fn last_notification(&self, phone: &str) -> Option<String> {
+        self.notifications.lock().unwrap().get(phone).cloned()
+    }
+}
+
+#[async_trait]
+impl MessageProvider for LocalMessageProvider {
+    async fn send_verification_code(&self, phone: &PhoneNumber, code: &str) -> Result<()> {
+        info!("verification code {code} has been sent to {phone}");
+
+        self.codes.lock().unwrap().insert(phone.to_string(), code.to_string());
+
+        Ok(())
+    }
+
+    async fn send_notification(&self, phone: &PhoneNumber, message: &str) -> Result<()> {
+        info!("notification \"{message}\" has been sent to {phone}");
+
+        self.notifications.lock().unwrap().insert(phone.to_string(), message.to_string());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+mod aligo;
+mod local;
+
+use std::sync::Arc;
+
+use axum::async_trait;
+
+use crate::{env::get_env_or_panic, user::phone::PhoneNumber, Result};
+
+pub use aligo::{AligoAlimtalkProvider, AligoSmsProvider};
+pub use local::LocalMessageProvider;
+
+/// A service delivering messages to the phone of a user.
+#[async_trait]
+pub trait MessageProvider: Send + Sync {
+    async fn send_verification_code(&self, phone: &PhoneNumber, code: &str) -> Result<()>;
+
+    /// Sends a free-form notice about the account, such as a review result.
+    async fn send_notification(&self, phone: &PhoneNumber, message: &str) -> Result<()>;
+}
+
+/// Creates the message provider selected by `MESSAGE_PROVIDER`.
+///
+/// Available providers are `alimtalk`, `sms` and `local`.
+pub fn from_env() -> Arc<dyn MessageProvider> {
+    match get_env_or_panic("MESSAGE_PROVIDER").as_str() {
+        "alimtalk" => Arc::new(AligoAlimtalkProvider::from_env()),
+        "sms" => Arc::new(AligoSmsProvider::from_env()),
+        "local" => Arc::new(LocalMessageProvider::new()),
+        provider => panic!("Unknown message provider: {provider}"),
+    }
+}
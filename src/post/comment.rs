@@ -1,14 +1,14 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
 use chrono::{DateTime, Utc};
-use sqlx::MySql;
+use sqlx::{MySql, QueryBuilder};
 
 use crate::{
     user::account::{User, UserId},
     Error, Result,
 };
 
-use super::PostId;
+use super::{search, PostId};
 
 pub(crate) type CommentId = u64;
 
@@ -84,7 +84,7 @@ ORDER BY c.created_at ASC",
         parent_comment_id: Option<CommentId>,
         db: &sqlx::Pool<MySql>,
     ) -> Result<Self> {
-        let tx = db.begin().await?;
+        let mut tx = db.begin().await?;
 
         let id = sqlx::query!(
             "INSERT INTO post_comment (post_id, author_id, content) VALUES (?, ?, ?)",
@@ -92,11 +92,13 @@ ORDER BY c.created_at ASC",
             author.id(),
             content
         )
-        .execute(db)
+        .execute(&mut *tx)
         .await
         .map(|row| row.last_insert_id())?;
         let parent_comment_id = parent_comment_id.unwrap_or(id);
 
+        search::index_comment(&mut *tx, id, content).await?;
+
         sqlx::query!(
             "INSERT INTO post_comment_closure (parent_comment_id, child_comment_id)
             SELECT cs.parent_comment_id, ? FROM post_comment_closure AS cs WHERE cs.child_comment_id = ?
@@ -106,14 +108,12 @@ ORDER BY c.created_at ASC",
             id,
             id
             )
-            .execute(db)
+            .execute(&mut *tx)
             .await?;
 
-        let comment = Self::from_id(id, author, db).await?;
-
         tx.commit().await?;
 
-        Ok(comment)
+        Self::from_id(id, author, db).await
     }
 
     pub(crate) async fn delete(id: CommentId, db: &sqlx::Pool<MySql>) -> Result<()> {
@@ -181,6 +181,67 @@ FROM post_comment WHERE id = ?",
         .ok_or(Error::CommentNotFound(id))
     }
 
+    /// Comments written by `user`, newest first.
+    pub(crate) async fn from_author(
+        user: &User,
+        last_id: CommentId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+id,
+post_id,
+author_id as `author_id: _`,
+content,
+deleted as `deleted: _`,
+created_at
+FROM post_comment WHERE author_id = ? AND id < ?
+ORDER BY id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    /// Comments of the post containing all of the search `tokens`, newest
+    /// first, leaving out the deleted and blocked ones.
+    pub(crate) async fn search_in_post(
+        post_id: PostId,
+        user: &User,
+        tokens: &[String],
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut sql = QueryBuilder::<MySql>::new(
+            "SELECT
+pc.id,
+pc.post_id,
+pc.author_id,
+pc.content,
+pc.deleted,
+pc.created_at
+FROM post_comment as pc WHERE pc.post_id = ",
+        );
+        sql.push_bind(post_id);
+        sql.push(" AND ");
+        search::push_visible_comment(&mut sql, user);
+        sql.push(
+            " AND pc.id IN (
+    SELECT ct.comment_id FROM post_comment_search_token as ct WHERE ct.token IN ",
+        );
+        search::push_tokens(&mut sql, tokens);
+        sql.push(" GROUP BY ct.comment_id HAVING COUNT(*) = ");
+        sql.push_bind(tokens.len() as i64);
+        sql.push(") ORDER BY pc.id DESC LIMIT ");
+        sql.push_bind(limit);
+
+        Ok(sql.build_query_as::<Self>().persistent(false).fetch_all(db).await?)
+    }
+
     pub(crate) fn id(&self) -> CommentId {
         self.id
     }
@@ -0,0 +1,534 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use sqlx::{MySql, MySqlExecutor};
+use tracing::{error, info};
+
+use crate::{
+    user::{
+        account::{User, UserId},
+        profile,
+    },
+    AppState, Error, Result,
+};
+
+use super::{GatheringAgeRange, Post, PostId, PostType};
+
+pub(crate) type ParticipantId = u64;
+
+/// Time zone of gatherings created without one
+const DEFAULT_TIMEZONE: Tz = Tz::Asia__Seoul;
+/// Length of gatherings created without an end
+const DEFAULT_DURATION_HOURS: i64 = 2;
+const MAX_DURATION_HOURS: i64 = 24;
+/// How far ahead a gathering can be scheduled
+const MAX_SCHEDULE_DAYS: i64 = 365;
+
+/// How often the closing job looks for ended gatherings
+const CLOSING_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Feed filter on the schedule of gatherings. Gatherings created before
+/// schedules were introduced match neither.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GatheringFilter {
+    /// Gatherings which have not ended yet
+    Upcoming,
+    /// Gatherings which have ended
+    Past,
+}
+
+/// When a gathering takes place. Times are stored in UTC, and the time zone
+/// is kept to show them as the author entered them.
+pub(crate) struct GatheringSchedule {
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    timezone: Tz,
+}
+
+impl GatheringSchedule {
+    /// Parses the schedule of a new gathering. Times are either RFC 3339 or
+    /// local `YYYY-MM-DDTHH:MM[:SS]` times in `timezone`, an IANA time zone
+    /// name. Gatherings are left unscheduled without `starts_at`, as clients
+    /// from before schedules create them.
+    pub(crate) fn validate(
+        starts_at: Option<&str>,
+        ends_at: Option<&str>,
+        timezone: Option<&str>,
+    ) -> Result<Option<Self>> {
+        let timezone = match timezone {
+            Some(timezone) => parse_timezone(timezone)?,
+            None => DEFAULT_TIMEZONE,
+        };
+
+        let starts_at = match (starts_at, ends_at) {
+            (Some(starts_at), _) => parse_time("starts_at", starts_at, timezone)?,
+            (None, Some(_)) => {
+                return Err(Error::InvalidField {
+                    field: "ends_at",
+                    reason: "must be given with starts_at".to_string(),
+                })
+            }
+            (None, None) => return Ok(None),
+        };
+        let now = Utc::now();
+
+        if starts_at <= now {
+            return Err(Error::InvalidField {
+                field: "starts_at",
+                reason: "must be in the future".to_string(),
+            });
+        }
+        if starts_at > now + chrono::Duration::days(MAX_SCHEDULE_DAYS) {
+            return Err(Error::InvalidField {
+                field: "starts_at",
+                reason: format!("must be within {MAX_SCHEDULE_DAYS} days"),
+            });
+        }
+
+        let ends_at = match ends_at {
+            Some(ends_at) => parse_time("ends_at", ends_at, timezone)?,
+            None => starts_at + chrono::Duration::hours(DEFAULT_DURATION_HOURS),
+        };
+
+        let schedule = Self { starts_at, ends_at, timezone };
+        schedule.check_duration()?;
+
+        Ok(Some(schedule))
+    }
+
+    /// The schedule of the post, unless it is unscheduled.
+    pub(crate) fn from_post(post: &Post) -> Option<Self> {
+        Some(Self {
+            starts_at: post.starts_at()?,
+            ends_at: post.ends_at()?,
+            timezone: post
+                .timezone()
+                .and_then(|timezone| timezone.parse().ok())
+                .unwrap_or(DEFAULT_TIMEZONE),
+        })
+    }
+
+    /// Changes the end or the time zone, keeping the start. Only the given
+    /// fields are checked, so that the end of a gathering which has started
+    /// can still be extended or fixed.
+    pub(crate) fn update(mut self, ends_at: Option<&str>, timezone: Option<&str>) -> Result<Self> {
+        if let Some(timezone) = timezone {
+            self.timezone = parse_timezone(timezone)?;
+        }
+        if let Some(ends_at) = ends_at {
+            self.ends_at = parse_time("ends_at", ends_at, self.timezone)?;
+
+            if self.ends_at <= Utc::now() {
+                return Err(Error::InvalidField {
+                    field: "ends_at",
+                    reason: "must be in the future".to_string(),
+                });
+            }
+            self.check_duration()?;
+        }
+
+        Ok(self)
+    }
+
+    fn check_duration(&self) -> Result<()> {
+        if self.ends_at <= self.starts_at {
+            return Err(Error::InvalidField {
+                field: "ends_at",
+                reason: "must be after starts_at".to_string(),
+            });
+        }
+        if self.ends_at > self.starts_at + chrono::Duration::hours(MAX_DURATION_HOURS) {
+            return Err(Error::InvalidField {
+                field: "ends_at",
+                reason: format!("must be at most {MAX_DURATION_HOURS} hours after starts_at"),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Closes ended gatherings periodically in the background.
+    pub(crate) fn spawn_closing_job(state: Arc<AppState>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLOSING_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(err) = Self::close_ended(&state.database).await {
+                    error!("failed to close ended gatherings: {err:?}");
+                }
+            }
+        });
+    }
+
+    async fn close_ended(db: &sqlx::Pool<MySql>) -> Result<()> {
+        let total = sqlx::query!(
+            "UPDATE post SET closed_at = NOW()
+WHERE post_type = ? AND closed_at IS NULL AND ends_at <= NOW()",
+            PostType::Gathering
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        if total > 0 {
+            info!("closed {total} ended gatherings");
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn starts_at(&self) -> DateTime<Utc> {
+        self.starts_at
+    }
+
+    pub(crate) fn ends_at(&self) -> DateTime<Utc> {
+        self.ends_at
+    }
+
+    pub(crate) fn timezone(&self) -> &'static str {
+        self.timezone.name()
+    }
+}
+
+fn parse_timezone(timezone: &str) -> Result<Tz> {
+    timezone.trim().parse().map_err(|_| Error::InvalidField {
+        field: "timezone",
+        reason: "must be an IANA time zone name".to_string(),
+    })
+}
+
+fn parse_time(field: &'static str, value: &str, timezone: Tz) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let invalid = |reason: &str| Error::InvalidField { field, reason: reason.to_string() };
+    let time = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .map_err(|_| invalid("must be an RFC 3339 or a YYYY-MM-DDTHH:MM time"))?;
+
+    // A time repeated when the clocks go back is taken at its first occurrence
+    timezone
+        .from_local_datetime(&time)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| invalid("does not exist in the time zone"))
+}
+
+/// A user who joined a gathering post.
+pub(crate) struct Participant {
+    id: ParticipantId,
+    user_id: UserId,
+    post_id: PostId,
+    created_at: DateTime<Utc>,
+}
+
+impl Participant {
+    /// Joins the gathering if a seat is left and the age of the user is in
+    /// its age range. Joining again does nothing.
+    pub(crate) async fn join(post: &Post, user: &User, db: &sqlx::Pool<MySql>) -> Result<()> {
+        if !matches!(post.post_type(), PostType::Gathering) {
+            return Err(Error::NotGathering(post.id()));
+        }
+        // The author hosts the gathering, and does not take a seat
+        if post.author_id() == user.id() {
+            return Err(Error::InvalidRequest);
+        }
+        if post.is_closed() {
+            return Err(Error::GatheringClosed(post.id()));
+        }
+        if let Some(age_range) = post.age_range() {
+            if !GatheringAgeRange::from_id(age_range, db)
+                .await?
+                .contains(profile::age(user.birthdate()))
+            {
+                return Err(Error::AgeRestricted(post.id()));
+            }
+        }
+
+        let mut tx = db.begin().await?;
+
+        // Locking the post makes concurrent joins wait for each other, so that
+        // the last seat cannot be taken twice
+        let capacity =
+            sqlx::query_scalar!("SELECT capacity FROM post WHERE id = ? FOR UPDATE", post.id())
+                .fetch_one(&mut *tx)
+                .await?;
+
+        if Self::exists(post.id(), user.id(), &mut *tx).await? {
+            return Ok(());
+        }
+        if let Some(capacity) = capacity {
+            if Self::count(post.id(), &mut *tx).await? >= i64::from(capacity) {
+                return Err(Error::GatheringFull(post.id()));
+            }
+        }
+
+        sqlx::query!(
+            "INSERT INTO gathering_participant (post_id, user_id) VALUES (?, ?)",
+            post.id(),
+            user.id()
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Leaves the gathering, which is only possible until it is closed.
+    pub(crate) async fn leave(post: &Post, user: &User, db: &sqlx::Pool<MySql>) -> Result<()> {
+        if post.is_closed() {
+            return Err(Error::GatheringClosed(post.id()));
+        }
+
+        sqlx::query!(
+            "DELETE FROM gathering_participant WHERE post_id = ? AND user_id = ?",
+            post.id(),
+            user.id()
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Participants who take a seat, leaving out deleted accounts.
+    pub(crate) async fn count<'e>(
+        post_id: PostId,
+        executor: impl MySqlExecutor<'e>,
+    ) -> Result<i64> {
+        Ok(sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM gathering_participant as gp
+INNER JOIN user as u ON u.id = gp.user_id WHERE gp.post_id = ? AND u.deleted = FALSE",
+            post_id
+        )
+        .fetch_one(executor)
+        .await?)
+    }
+
+    pub(crate) async fn exists<'e>(
+        post_id: PostId,
+        user_id: UserId,
+        executor: impl MySqlExecutor<'e>,
+    ) -> Result<bool> {
+        Ok(sqlx::query!(
+            "SELECT id FROM gathering_participant WHERE post_id = ? AND user_id = ?",
+            post_id,
+            user_id
+        )
+        .fetch_optional(executor)
+        .await?
+        .is_some())
+    }
+
+    /// Participants of the gathering, as seen by `user`, newest first.
+    pub(crate) async fn from_post_id(
+        post_id: PostId,
+        user: &User,
+        last_id: ParticipantId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+gp.id as `id: ParticipantId`,
+gp.user_id as `user_id: UserId`,
+gp.post_id as `post_id: PostId`,
+gp.created_at
+FROM gathering_participant as gp INNER JOIN user as u ON u.id = gp.user_id WHERE
+gp.post_id = ? AND gp.id < ? AND u.deleted = FALSE AND
+gp.user_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?)
+ORDER BY gp.id DESC LIMIT ?",
+            post_id,
+            last_id,
+            user.id(),
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    /// Gatherings joined by `user`, newest first.
+    pub(crate) async fn from_user(
+        user: &User,
+        last_id: ParticipantId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+id as `id: ParticipantId`,
+user_id as `user_id: UserId`,
+post_id as `post_id: PostId`,
+created_at
+FROM gathering_participant WHERE user_id = ? AND id < ?
+ORDER BY id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) fn id(&self) -> ParticipantId {
+        self.id
+    }
+
+    pub(crate) fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    pub(crate) fn post_id(&self) -> PostId {
+        self.post_id
+    }
+
+    pub(crate) fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn is_invalid<T>(result: Result<T>, expected: &str) -> bool {
+        matches!(result, Err(Error::InvalidField { field, .. }) if field == expected)
+    }
+
+    #[test]
+    fn times_are_rfc3339_or_local() {
+        let expected = utc("2024-03-10T01:00:00Z");
+
+        assert_eq!(
+            parse_time("starts_at", "2024-03-10T10:00:00+09:00", Tz::UTC).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_time("starts_at", " 2024-03-10T10:00 ", Tz::Asia__Seoul).unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_time("starts_at", "2024-03-10T10:00:00", Tz::Asia__Seoul).unwrap(),
+            expected
+        );
+        assert!(is_invalid(parse_time("starts_at", "2024-03-10 10:00", Tz::UTC), "starts_at"));
+    }
+
+    #[test]
+    fn local_times_skipped_by_daylight_saving_do_not_exist() {
+        let result = parse_time("starts_at", "2024-03-10T02:30", Tz::America__New_York);
+
+        assert!(is_invalid(result, "starts_at"));
+    }
+
+    #[test]
+    fn repeated_local_times_are_taken_at_their_first_occurrence() {
+        let time = parse_time("ends_at", "2024-11-03T01:30", Tz::America__New_York).unwrap();
+
+        assert_eq!(time, utc("2024-11-03T05:30:00Z"));
+    }
+
+    #[test]
+    fn schedule_is_optional() {
+        assert!(GatheringSchedule::validate(None, None, None).unwrap().is_none());
+        assert!(GatheringSchedule::validate(None, None, Some("Asia/Seoul")).unwrap().is_none());
+        assert!(is_invalid(
+            GatheringSchedule::validate(None, Some("2024-03-10T10:00"), None),
+            "ends_at"
+        ));
+    }
+
+    #[test]
+    fn schedule_starts_in_the_future_within_a_year() {
+        let past = (Utc::now() - Duration::hours(1)).to_rfc3339();
+        let far = (Utc::now() + Duration::days(MAX_SCHEDULE_DAYS + 1)).to_rfc3339();
+
+        assert!(is_invalid(GatheringSchedule::validate(Some(&past), None, None), "starts_at"));
+        assert!(is_invalid(GatheringSchedule::validate(Some(&far), None, None), "starts_at"));
+    }
+
+    #[test]
+    fn schedule_ends_after_its_start_within_a_day() {
+        let starts_at = Utc::now() + Duration::days(1);
+        let start = starts_at.to_rfc3339();
+        let end = |hours| (starts_at + Duration::hours(hours)).to_rfc3339();
+
+        let schedule = GatheringSchedule::validate(Some(&start), None, None).unwrap().unwrap();
+        assert_eq!(schedule.ends_at() - schedule.starts_at(), Duration::hours(2));
+        assert_eq!(schedule.timezone(), "Asia/Seoul");
+
+        let schedule =
+            GatheringSchedule::validate(Some(&start), Some(&end(MAX_DURATION_HOURS)), None);
+        assert!(schedule.is_ok());
+        assert!(is_invalid(
+            GatheringSchedule::validate(Some(&start), Some(&end(0)), None),
+            "ends_at"
+        ));
+        assert!(is_invalid(
+            GatheringSchedule::validate(Some(&start), Some(&end(MAX_DURATION_HOURS + 1)), None),
+            "ends_at"
+        ));
+    }
+
+    #[test]
+    fn schedule_time_zone_is_an_iana_name() {
+        let start = (Utc::now() + Duration::days(1)).to_rfc3339();
+
+        assert!(is_invalid(
+            GatheringSchedule::validate(Some(&start), None, Some("KST")),
+            "timezone"
+        ));
+    }
+
+    fn started(now: DateTime<Utc>) -> GatheringSchedule {
+        GatheringSchedule {
+            starts_at: now - Duration::hours(1),
+            ends_at: now + Duration::hours(1),
+            timezone: DEFAULT_TIMEZONE,
+        }
+    }
+
+    #[test]
+    fn started_schedule_can_be_extended() {
+        let now = Utc::now();
+        let end = |hours| (now + Duration::hours(hours)).to_rfc3339();
+
+        let extended = started(now).update(Some(&end(3)), None).unwrap();
+        assert_eq!(extended.starts_at(), now - Duration::hours(1));
+        assert_eq!(extended.ends_at(), now + Duration::hours(3));
+
+        let moved = started(now).update(None, Some("UTC")).unwrap();
+        assert_eq!(moved.timezone(), "UTC");
+        assert_eq!(moved.ends_at(), now + Duration::hours(1));
+    }
+
+    #[test]
+    fn started_schedule_still_ends_in_the_future_within_a_day() {
+        let now = Utc::now();
+        let end = |hours| (now + Duration::hours(hours)).to_rfc3339();
+
+        assert!(is_invalid(started(now).update(Some(&end(-1)), None), "ends_at"));
+        assert!(is_invalid(started(now).update(Some(&end(MAX_DURATION_HOURS)), None), "ends_at"));
+        assert!(is_invalid(started(now).update(None, Some("Seoul")), "timezone"));
+    }
+}
@@ -1,29 +1,38 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
 pub(crate) mod comment;
+pub(crate) mod gathering;
+pub(crate) mod search;
 
 use axum::{async_trait, body};
 use axum_typed_multipart::{FieldData, FieldMetadata, TryFromChunks, TypedMultipartError};
 use chrono::{DateTime, Utc};
 use rand::{distributions::Alphanumeric, Rng};
-use serde_repr::Serialize_repr;
-use sqlx::{MySql, QueryBuilder};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use sqlx::{MySql, MySqlExecutor, QueryBuilder};
 use tempfile::NamedTempFile;
 use tokio::fs;
+use tracing::warn;
 
 use crate::{
     aws::S3Client,
-    schema::PostCreationSchema,
+    schema::{PostCreationSchema, PostEditSchema, PostListSchema},
     town::TownId,
     user::account::{User, UserId},
     Error, Result,
 };
 
+use self::{
+    gathering::{GatheringFilter, GatheringSchedule, Participant},
+    search,
+};
+
 pub(crate) type PostId = u64;
+pub(crate) type PostImageId = u64;
 
 const POST_IMAGE_PATH: &str = "post_image/";
 
-#[derive(Clone, Copy, sqlx::Type, Serialize_repr)]
+#[derive(Clone, Copy, sqlx::Type, Serialize_repr, Deserialize_repr)]
 #[repr(u32)]
 pub enum PostType {
     Daily = 1,
@@ -67,21 +76,37 @@ pub(crate) struct Post {
     age_range: Option<u32>,
     capacity: Option<u32>,
     place: Option<String>,
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+    timezone: Option<String>,
+    closed_at: Option<DateTime<Utc>>,
     total_likes: i64,
     total_comments: i64,
     created_at: DateTime<Utc>,
 }
 
 #[derive(sqlx::FromRow)]
-struct PostImage {
-    id: u64,
+pub(crate) struct PostImage {
+    id: PostImageId,
     #[allow(dead_code)]
     post_id: PostId,
     image_url: String,
     #[allow(dead_code)]
+    position: u32,
+    #[allow(dead_code)]
     created_at: DateTime<Utc>,
 }
 
+impl PostImage {
+    pub(crate) fn id(&self) -> PostImageId {
+        self.id
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.image_url
+    }
+}
+
 impl Post {
     pub(crate) async fn create(
         user: &User,
@@ -89,53 +114,69 @@ impl Post {
         db: &sqlx::Pool<MySql>,
         s3: &S3Client,
     ) -> Result<Self> {
-        let tx = db.begin().await?;
-
         let mut age_range_id: Option<u32> = None;
+        let mut schedule: Option<GatheringSchedule> = None;
 
         match data.post_type {
-            PostType::Gathering => match data.age_range {
-                Some(description) => {
-                    age_range_id = GatheringAgeRange::from_description(&description, db)
-                        .await
-                        .map(|row| Some(row.id))?;
-                }
-                _ => {
-                    age_range_id = Some(1);
+            PostType::Gathering => {
+                match data.age_range {
+                    Some(description) => {
+                        age_range_id = GatheringAgeRange::from_description(&description, db)
+                            .await
+                            .map(|row| Some(row.id))?;
+                    }
+                    _ => {
+                        age_range_id = Some(1);
+                    }
                 }
-            },
+
+                schedule = GatheringSchedule::validate(
+                    data.starts_at.as_deref(),
+                    data.ends_at.as_deref(),
+                    data.timezone.as_deref(),
+                )?;
+            }
             _ => {
                 data.capacity = None;
                 data.place = None;
             }
         };
 
+        let urls = Self::upload_images(data.images, s3).await?;
+
+        let mut tx = db.begin().await?;
+
         let id = sqlx::query!(
-            "INSERT INTO post (author_id, post_type, town_id, content, age_range, capacity, place) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO post (author_id, post_type, town_id, content, age_range, capacity, place, starts_at, ends_at, timezone) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             user.id(),
             data.post_type,
             user.town_id(),
             data.content,
             age_range_id,
             data.capacity,
-            data.place
+            data.place,
+            schedule.as_ref().map(GatheringSchedule::starts_at),
+            schedule.as_ref().map(GatheringSchedule::ends_at),
+            schedule.as_ref().map(GatheringSchedule::timezone)
         )
-        .execute(db)
+        .execute(&mut *tx)
         .await
         .map(|row| row.last_insert_id())?;
-        let post = Self::from_id(id, user, db).await?;
-        post.upload_images(data.images, db, s3).await?;
+        search::index_post(&mut *tx, id, &data.content, data.place.as_deref()).await?;
+        Self::insert_images(id, &urls, 0, &mut *tx).await?;
 
         tx.commit().await?;
 
-        Ok(post)
+        Self::from_id(id, user, db).await
     }
 
+    /// Updates the fields given in `data`, applying the rules of the post
+    /// type as `create` does. The images kept are reordered as listed, and
+    /// the new ones appended after them.
     pub(crate) async fn edit(
         mut self,
         author_id: UserId,
-        content: &str,
-        images: Vec<FieldData<NamedTempFile>>,
+        data: PostEditSchema,
         db: &sqlx::Pool<MySql>,
         s3: &S3Client,
     ) -> Result<Self> {
@@ -143,25 +184,202 @@ impl Post {
             return Err(Error::PostNotFound(self.id()));
         }
 
-        let tx = db.begin().await?;
+        let was_gathering = matches!(self.post_type, PostType::Gathering);
+        let post_type = data.post_type.unwrap_or(self.post_type);
+
+        match post_type {
+            PostType::Gathering => {
+                if let Some(description) = data.age_range {
+                    self.age_range =
+                        Some(GatheringAgeRange::from_description(&description, db).await?.id);
+                } else if !was_gathering {
+                    self.age_range = Some(1);
+                }
+                if data.capacity.is_some() {
+                    self.capacity = data.capacity;
+                }
+                if data.place.is_some() {
+                    self.place = data.place;
+                }
+
+                let changed =
+                    data.starts_at.is_some() || data.ends_at.is_some() || data.timezone.is_some();
+
+                if changed || !was_gathering {
+                    let current =
+                        if was_gathering { GatheringSchedule::from_post(&self) } else { None };
+                    let schedule = match current {
+                        // Keeping the start, which may have passed already
+                        Some(current) if data.starts_at.is_none() => Some(
+                            current.update(data.ends_at.as_deref(), data.timezone.as_deref())?,
+                        ),
+                        // A new start without an end gets the default duration
+                        _ => GatheringSchedule::validate(
+                            data.starts_at.as_deref(),
+                            data.ends_at.as_deref(),
+                            data.timezone.as_deref().or(self.timezone.as_deref()),
+                        )?,
+                    };
+
+                    self.starts_at = schedule.as_ref().map(GatheringSchedule::starts_at);
+                    self.ends_at = schedule.as_ref().map(GatheringSchedule::ends_at);
+                    self.timezone =
+                        schedule.as_ref().map(|schedule| schedule.timezone().to_string());
+                    self.closed_at = None;
+                }
+            }
+            _ => {
+                self.age_range = None;
+                self.capacity = None;
+                self.place = None;
+                self.starts_at = None;
+                self.ends_at = None;
+                self.timezone = None;
+                self.closed_at = None;
+            }
+        }
+        self.post_type = post_type;
+        if let Some(content) = data.content {
+            self.content = content;
+        }
+
+        let current = self.images(db).await?;
+        let kept: Vec<&PostImage> = match data.image_ids.as_deref() {
+            Some(image_ids) => parse_image_ids(image_ids)?
+                .into_iter()
+                .map(|id| {
+                    current.iter().find(|image| image.id == id).ok_or_else(|| Error::InvalidField {
+                        field: "image_ids",
+                        reason: format!("image id {id} is not an image of the post"),
+                    })
+                })
+                .collect::<Result<_>>()?,
+            None => current.iter().collect(),
+        };
+        let removed: Vec<&PostImage> =
+            current.iter().filter(|image| !kept.iter().any(|kept| kept.id == image.id)).collect();
+
+        let urls = Self::upload_images(data.images, s3).await?;
+
+        if let Err(err) = self.save(author_id, &kept, &removed, &urls, db).await {
+            // Nothing refers to the new images, which would be left over
+            for key in urls.iter().filter_map(|url| s3.key_from_url(url)) {
+                if let Err(err) = s3.delete_file(key).await {
+                    warn!("failed to delete unused post image {key}: {err:?}");
+                }
+            }
+
+            return Err(err);
+        }
+
+        // The rows are gone already, so a file failing to be deleted is only left over
+        for key in removed.iter().filter_map(|image| s3.key_from_url(&image.image_url)) {
+            if let Err(err) = s3.delete_file(key).await {
+                warn!("failed to delete removed post image {key}: {err:?}");
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Writes the edited fields and images at once, once the participants
+    /// still fit in the gathering.
+    async fn save(
+        &self,
+        author_id: UserId,
+        kept: &[&PostImage],
+        removed: &[&PostImage],
+        new_urls: &[String],
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
+        let mut tx = db.begin().await?;
+
+        // Locking the post makes joins wait as in `Participant::join`, so that
+        // no one can join between the check and the update
+        sqlx::query!("SELECT id FROM post WHERE id = ? FOR UPDATE", self.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let total_participants = Participant::count(self.id, &mut *tx).await?;
+        match self.post_type {
+            PostType::Gathering => {
+                if let Some(capacity) = self.capacity {
+                    if i64::from(capacity) < total_participants {
+                        return Err(Error::InvalidField {
+                            field: "capacity",
+                            reason: format!(
+                                "must not be less than the {total_participants} participants"
+                            ),
+                        });
+                    }
+                }
+            }
+            _ => {
+                if total_participants > 0 {
+                    return Err(Error::InvalidField {
+                        field: "post_type",
+                        reason: "cannot be changed once the gathering has participants".to_string(),
+                    });
+                }
+            }
+        }
 
         sqlx::query!(
-            "UPDATE post SET content = ? WHERE id = ? AND author_id = ?",
-            content,
+            "UPDATE post SET
+post_type = ?,
+content = ?,
+age_range = ?,
+capacity = ?,
+place = ?,
+starts_at = ?,
+ends_at = ?,
+timezone = ?,
+closed_at = ?
+WHERE id = ? AND author_id = ?",
+            self.post_type,
+            self.content,
+            self.age_range,
+            self.capacity,
+            self.place,
+            self.starts_at,
+            self.ends_at,
+            self.timezone,
+            self.closed_at,
             self.id,
             author_id
         )
-        .execute(db)
+        .execute(&mut *tx)
         .await?;
 
-        self.delete_images(db, s3).await?;
-        self.upload_images(images, db, s3).await?;
+        search::index_post(&mut *tx, self.id, &self.content, self.place.as_deref()).await?;
 
-        tx.commit().await?;
+        if !removed.is_empty() {
+            let mut sql = QueryBuilder::<MySql>::new("DELETE FROM post_image WHERE id IN (");
 
-        self.content = content.to_string();
+            let mut separated = sql.separated(", ");
+            removed.iter().for_each(|image| {
+                separated.push_bind(image.id);
+            });
+            separated.push_unseparated(")");
 
-        Ok(self)
+            sql.build().persistent(false).execute(&mut *tx).await?;
+        }
+
+        for (position, image) in kept.iter().enumerate() {
+            sqlx::query!(
+                "UPDATE post_image SET position = ? WHERE id = ?",
+                position as u32,
+                image.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        Self::insert_images(self.id, new_urls, kept.len() as u32, &mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(())
     }
 
     pub(crate) async fn delete(
@@ -174,15 +392,20 @@ impl Post {
             return Err(Error::PostNotFound(self.id()));
         }
 
-        sqlx::query!("DELETE FROM post WHERE id = ? AND author_id = ?", self.id, self.author_id)
-            .execute(db)
-            .await?;
+        self.remove(db, s3).await
+    }
 
+    /// Deletes the post regardless of its author.
+    pub(crate) async fn remove(self, db: &sqlx::Pool<MySql>, s3: &S3Client) -> Result<()> {
         self.delete_images(db, s3).await?;
 
+        sqlx::query!("DELETE FROM post WHERE id = ?", self.id).execute(db).await?;
+
         Ok(())
     }
 
+    /// Finds a post in the town of the user. Authors who moved to another
+    /// town can still reach the posts left in their previous one.
     pub(crate) async fn from_id(id: u64, user: &User, db: &sqlx::Pool<MySql>) -> Result<Self> {
         sqlx::query_as!(
             Self,
@@ -194,16 +417,22 @@ content,
 age_range,
 capacity,
 place,
+starts_at,
+ends_at,
+timezone,
+closed_at,
 (SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as `total_likes!`,
 (SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as `total_comments!`,
 created_at FROM post as p WHERE
-id = ? AND town_id = ? AND
+id = ? AND (town_id = ? OR author_id = ?) AND
+author_id NOT IN (SELECT id FROM user WHERE deleted = TRUE) AND
 author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?) AND
 id NOT IN (SELECT post_id FROM post_block WHERE user_id = ?)
 ",
             id,
             user.town_id(),
             user.id(),
+            user.id(),
             user.id()
         )
         .fetch_one(db)
@@ -229,6 +458,10 @@ content,
 age_range,
 capacity,
 place,
+starts_at,
+ends_at,
+timezone,
+closed_at,
 (SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as `total_likes!`,
 (SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as `total_comments!`,
 created_at FROM post as p WHERE
@@ -244,6 +477,36 @@ id = ? AND town_id = ?",
         })
     }
 
+    /// Finds a post in any town, for moderation.
+    pub(crate) async fn from_id_unscoped(id: u64, db: &sqlx::Pool<MySql>) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            "SELECT id,
+author_id,
+post_type,
+town_id,
+content,
+age_range,
+capacity,
+place,
+starts_at,
+ends_at,
+timezone,
+closed_at,
+(SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as `total_likes!`,
+(SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as `total_comments!`,
+created_at FROM post as p WHERE id = ?",
+            id,
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => Error::PostNotFound(id),
+            _ => Error::Database(err),
+        })
+    }
+
+    /// Posts written by the user in every town the user has lived in.
     pub(crate) async fn from_user(
         user: &User,
         last_id: PostId,
@@ -260,16 +523,19 @@ content,
 age_range,
 capacity,
 place,
+starts_at,
+ends_at,
+timezone,
+closed_at,
 (SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as `total_likes!`,
 (SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as `total_comments!`,
 created_at
 FROM post as p WHERE
-id < ? AND town_id = ? AND author_id = ? AND
+id < ? AND author_id = ? AND
 author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?) AND
 id NOT IN (SELECT post_id FROM post_block WHERE user_id = ?)
 ORDER BY id DESC LIMIT ?",
             last_id,
-            user.town_id(),
             user.id(),
             user.id(),
             user.id(),
@@ -279,7 +545,10 @@ ORDER BY id DESC LIMIT ?",
         .await?)
     }
 
-    pub(crate) async fn get(
+    /// Posts written by `author` as seen by `user`, which are only those in
+    /// the town of the user unless the user is the author.
+    pub(crate) async fn from_author(
+        author: &User,
         user: &User,
         last_id: PostId,
         limit: i32,
@@ -295,24 +564,112 @@ content,
 age_range,
 capacity,
 place,
+starts_at,
+ends_at,
+timezone,
+closed_at,
 (SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as `total_likes!`,
 (SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as `total_comments!`,
 created_at
 FROM post as p WHERE
-id < ? AND town_id = ? AND
+id < ? AND author_id = ? AND (town_id = ? OR author_id = ?) AND
 author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?) AND
 id NOT IN (SELECT post_id FROM post_block WHERE user_id = ?)
 ORDER BY id DESC LIMIT ?",
             last_id,
+            author.id(),
             user.town_id(),
             user.id(),
             user.id(),
+            user.id(),
             limit
         )
         .fetch_all(db)
         .await?)
     }
 
+    /// Posts in the town of the user matching the filters of `params`,
+    /// newest first.
+    pub(crate) async fn get(
+        user: &User,
+        params: &PostListSchema,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut sql = QueryBuilder::<MySql>::new(
+            "SELECT id,
+author_id,
+post_type,
+town_id,
+content,
+age_range,
+capacity,
+place,
+starts_at,
+ends_at,
+timezone,
+closed_at,
+(SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as total_likes,
+(SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as total_comments,
+created_at
+FROM post as p WHERE
+author_id NOT IN (SELECT id FROM user WHERE deleted = TRUE) AND
+author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ",
+        );
+        sql.push_bind(user.id());
+        sql.push(") AND id NOT IN (SELECT post_id FROM post_block WHERE user_id = ");
+        sql.push_bind(user.id());
+        sql.push(") AND town_id = ");
+        sql.push_bind(user.town_id());
+        sql.push(" AND id < ");
+        sql.push_bind(params.last_id());
+
+        if let Some(post_type) = params.post_type {
+            sql.push(" AND post_type = ");
+            sql.push_bind(post_type);
+        }
+        if let Some(author_id) = params.author_id {
+            sql.push(" AND author_id = ");
+            sql.push_bind(author_id);
+        }
+        if let Some(since) = params.since {
+            sql.push(" AND created_at >= ");
+            sql.push_bind(since);
+        }
+        if let Some(until) = params.until {
+            sql.push(" AND created_at < ");
+            sql.push_bind(until);
+        }
+        match params.gathering {
+            Some(GatheringFilter::Upcoming) => {
+                sql.push(" AND post_type = ");
+                sql.push_bind(PostType::Gathering);
+                sql.push(" AND closed_at IS NULL AND ends_at > NOW()");
+            }
+            Some(GatheringFilter::Past) => {
+                sql.push(" AND post_type = ");
+                sql.push_bind(PostType::Gathering);
+                sql.push(" AND (closed_at IS NOT NULL OR ends_at <= NOW())");
+            }
+            None => (),
+        }
+        match params.has_images {
+            Some(true) => {
+                sql.push(" AND EXISTS (SELECT id FROM post_image as pi WHERE pi.post_id = p.id)");
+            }
+            Some(false) => {
+                sql.push(
+                    " AND NOT EXISTS (SELECT id FROM post_image as pi WHERE pi.post_id = p.id)",
+                );
+            }
+            None => (),
+        }
+
+        sql.push(" ORDER BY id DESC LIMIT ");
+        sql.push_bind(params.limit());
+
+        Ok(sql.build_query_as::<Self>().persistent(false).fetch_all(db).await?)
+    }
+
     pub(crate) fn id(&self) -> PostId {
         self.id
     }
@@ -345,6 +702,28 @@ ORDER BY id DESC LIMIT ?",
         self.place.as_deref()
     }
 
+    pub(crate) fn starts_at(&self) -> Option<DateTime<Utc>> {
+        self.starts_at
+    }
+
+    pub(crate) fn ends_at(&self) -> Option<DateTime<Utc>> {
+        self.ends_at
+    }
+
+    pub(crate) fn timezone(&self) -> Option<&str> {
+        self.timezone.as_deref()
+    }
+
+    pub(crate) fn closed_at(&self) -> Option<DateTime<Utc>> {
+        self.closed_at
+    }
+
+    /// Whether the gathering has ended, even if the closing job has not
+    /// closed it yet.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed_at.is_some() || self.ends_at.is_some_and(|ends_at| ends_at <= Utc::now())
+    }
+
     pub(crate) fn total_likes(&self) -> i64 {
         self.total_likes
     }
@@ -357,21 +736,22 @@ ORDER BY id DESC LIMIT ?",
         self.created_at
     }
 
-    pub(crate) async fn images(&self, db: &sqlx::Pool<MySql>) -> Result<Vec<String>> {
-        Ok(sqlx::query_as!(PostImage, "SELECT * FROM post_image WHERE post_id = ?", self.id)
-            .fetch_all(db)
-            .await?
-            .iter()
-            .map(|image| image.image_url.clone())
-            .collect())
+    /// Images of the post in the order they are shown.
+    pub(crate) async fn images(&self, db: &sqlx::Pool<MySql>) -> Result<Vec<PostImage>> {
+        Ok(sqlx::query_as!(
+            PostImage,
+            "SELECT * FROM post_image WHERE post_id = ? ORDER BY position, id",
+            self.id
+        )
+        .fetch_all(db)
+        .await?)
     }
 
+    /// Uploads the images to the storage, returning their URLs.
     async fn upload_images(
-        &self,
         images: Vec<FieldData<NamedTempFile>>,
-        db: &sqlx::Pool<MySql>,
         s3: &S3Client,
-    ) -> Result<()> {
+    ) -> Result<Vec<String>> {
         let mut image_urls: Vec<String> = vec![];
 
         for image in images {
@@ -396,17 +776,27 @@ ORDER BY id DESC LIMIT ?",
             // }
         }
 
-        sqlx::query!("DELETE FROM post_image WHERE post_id = ?", self.id).execute(db).await?;
+        Ok(image_urls)
+    }
 
+    /// Adds the uploaded images after the existing ones, from `first_position`.
+    async fn insert_images<'e>(
+        id: PostId,
+        image_urls: &[String],
+        first_position: u32,
+        executor: impl MySqlExecutor<'e>,
+    ) -> Result<()> {
         if !image_urls.is_empty() {
-            let mut sql =
-                QueryBuilder::<MySql>::new("INSERT INTO post_image (post_id, image_url) ");
-            sql.push_values(image_urls.iter(), |mut sql, url| {
-                sql.push_bind(self.id);
+            let mut sql = QueryBuilder::<MySql>::new(
+                "INSERT INTO post_image (post_id, image_url, position) ",
+            );
+            sql.push_values(image_urls.iter().zip(first_position..), |mut sql, (url, position)| {
+                sql.push_bind(id);
                 sql.push_bind(url);
+                sql.push_bind(position);
             });
             let sql = sql.build().persistent(false);
-            sql.execute(db).await?;
+            sql.execute(executor).await?;
         }
 
         Ok(())
@@ -420,14 +810,9 @@ ORDER BY id DESC LIMIT ?",
         let mut deleted_ids: Vec<u64> = vec![];
 
         for image in images {
-            let url = image.image_url;
-            let parts: Vec<&str> = url.split('/').collect();
-
-            if parts.len() < 2 {
+            let Some(path) = s3.key_from_url(&image.image_url) else {
                 continue;
-            }
-
-            let path = parts[1];
+            };
 
             if s3.delete_file(path).await.is_ok() {
                 deleted_ids.push(image.id);
@@ -451,12 +836,27 @@ ORDER BY id DESC LIMIT ?",
     }
 }
 
+/// Parses the comma-separated ids of the images kept by an edit.
+fn parse_image_ids(image_ids: &str) -> Result<Vec<PostImageId>> {
+    let invalid = |reason: String| Error::InvalidField { field: "image_ids", reason };
+    let mut ids: Vec<PostImageId> = vec![];
+
+    for id in image_ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let id = id.parse().map_err(|_| invalid(format!("{id} is not an image id")))?;
+
+        if ids.contains(&id) {
+            return Err(invalid(format!("image id {id} is listed more than once")));
+        }
+        ids.push(id);
+    }
+
+    Ok(ids)
+}
+
 pub(crate) struct GatheringAgeRange {
     #[allow(dead_code)]
     id: u32,
-    #[allow(dead_code)]
     min_age: Option<u32>,
-    #[allow(dead_code)]
     max_age: Option<u32>,
     description: String,
 }
@@ -494,4 +894,39 @@ impl GatheringAgeRange {
     pub(crate) fn description(&self) -> &str {
         &self.description
     }
+
+    /// Whether `age` is in the range, whose bounds are inclusive and open
+    /// when missing.
+    pub(crate) fn contains(&self, age: i32) -> bool {
+        let age = i64::from(age);
+
+        !matches!(self.min_age, Some(min_age) if age < i64::from(min_age))
+            && !matches!(self.max_age, Some(max_age) if age > i64::from(max_age))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_ids_keep_their_order() {
+        assert_eq!(parse_image_ids("3, 1,2").unwrap(), [3, 1, 2]);
+        assert_eq!(parse_image_ids(" 3,,1, ").unwrap(), [3, 1]);
+        assert!(parse_image_ids("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn image_ids_are_listed_once() {
+        assert!(matches!(
+            parse_image_ids("1,2,1"),
+            Err(Error::InvalidField { field: "image_ids", .. })
+        ));
+    }
+
+    #[test]
+    fn image_ids_are_numbers() {
+        assert!(parse_image_ids("1,a").is_err());
+        assert!(parse_image_ids("-1").is_err());
+    }
 }
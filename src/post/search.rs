@@ -0,0 +1,443 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use serde::Serialize;
+use sqlx::{MySql, MySqlConnection, QueryBuilder};
+
+use crate::{user::account::User, Error, Result};
+
+use super::{
+    comment::{Comment, CommentId},
+    Post, PostId,
+};
+
+/// Shortest word that can be matched, as words are indexed by their bigrams.
+/// Shorter words of a query are left out of the search.
+const QUERY_MIN_LENGTH: usize = 2;
+const QUERY_MAX_LENGTH: usize = 100;
+
+/// Tokens inserted by a statement, keeping it under the placeholder limit
+const TOKEN_BATCH_SIZE: usize = 1000;
+
+/// Number of characters of a highlight snippet
+const SNIPPET_LENGTH: usize = 100;
+/// Characters shown before the first match in a snippet
+const SNIPPET_LEADING_LENGTH: usize = 30;
+
+/// Matching comments highlighted for each post
+const COMMENT_HIGHLIGHTS: i32 = 3;
+
+/// A post matching the search, ranked by the relevance of the post itself and
+/// of its best matching comment.
+#[derive(sqlx::FromRow)]
+pub(crate) struct SearchHit {
+    #[sqlx(flatten)]
+    post: Post,
+    score: f64,
+}
+
+/// Part of a matching field, with the character ranges of the search terms.
+#[derive(Debug, Serialize)]
+pub(crate) struct Highlight {
+    field: HighlightField,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment_id: Option<CommentId>,
+    snippet: String,
+    ranges: Vec<[usize; 2]>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HighlightField {
+    Content,
+    Place,
+    Comment,
+}
+
+/// Trims the query and checks that the index can match it.
+pub(crate) fn validate_query(query: &str) -> Result<String> {
+    let invalid = |reason: String| Error::InvalidField { field: "q", reason };
+    let query = query.trim();
+
+    if terms(query).is_empty() {
+        return Err(invalid(format!("must have a word of at least {QUERY_MIN_LENGTH} characters")));
+    }
+    if query.chars().count() > QUERY_MAX_LENGTH {
+        return Err(invalid(format!("must be at most {QUERY_MAX_LENGTH} characters")));
+    }
+
+    Ok(query.to_string())
+}
+
+/// Every two adjacent characters of the words in `text`, lowercased and
+/// deduplicated. A query matches a text containing all of its bigrams, which
+/// finds Korean words regardless of the particles attached to them.
+///
+/// The bigrams stand in for a full-text index with an ngram parser, which
+/// MariaDB does not have.
+pub(crate) fn tokens(text: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = text
+        .split_whitespace()
+        .flat_map(|word| {
+            let chars: Vec<char> = word.chars().map(fold_case).collect();
+
+            chars.windows(2).map(|pair| pair.iter().collect()).collect::<Vec<String>>()
+        })
+        .collect();
+
+    tokens.sort();
+    tokens.dedup();
+    tokens
+}
+
+/// Replaces the indexed tokens of the post with those of its content and
+/// place.
+pub(crate) async fn index_post(
+    conn: &mut MySqlConnection,
+    post_id: PostId,
+    content: &str,
+    place: Option<&str>,
+) -> Result<()> {
+    sqlx::query!("DELETE FROM post_search_token WHERE post_id = ?", post_id)
+        .execute(&mut *conn)
+        .await?;
+
+    // Joined by a line break, so that no bigram spans both fields
+    let tokens = tokens(&format!("{content}\n{}", place.unwrap_or_default()));
+
+    insert_tokens(conn, "post_search_token (post_id, token)", post_id, &tokens).await
+}
+
+/// Indexes the tokens of a new comment, whose content does not change.
+pub(crate) async fn index_comment(
+    conn: &mut MySqlConnection,
+    comment_id: CommentId,
+    content: &str,
+) -> Result<()> {
+    insert_tokens(
+        conn,
+        "post_comment_search_token (comment_id, token)",
+        comment_id,
+        &tokens(content),
+    )
+    .await
+}
+
+async fn insert_tokens(
+    conn: &mut MySqlConnection,
+    table: &str,
+    id: u64,
+    tokens: &[String],
+) -> Result<()> {
+    for batch in tokens.chunks(TOKEN_BATCH_SIZE) {
+        let mut sql = QueryBuilder::<MySql>::new(format!("INSERT INTO {table} "));
+        sql.push_values(batch, |mut sql, token| {
+            sql.push_bind(id);
+            sql.push_bind(token);
+        });
+        sql.build().persistent(false).execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Pushes the tokens as the list of an `IN` condition.
+pub(crate) fn push_tokens<'a>(sql: &mut QueryBuilder<'a, MySql>, tokens: &'a [String]) {
+    sql.push("(");
+    let mut separated = sql.separated(", ");
+    tokens.iter().for_each(|token| {
+        separated.push_bind(token);
+    });
+    separated.push_unseparated(")");
+}
+
+/// Pushes the conditions leaving out the comments `user` cannot see, on
+/// `post_comment` aliased as `pc`.
+pub(crate) fn push_visible_comment(sql: &mut QueryBuilder<'_, MySql>, user: &User) {
+    sql.push(
+        "pc.deleted = FALSE AND
+pc.author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ",
+    );
+    sql.push_bind(user.id());
+    sql.push(") AND pc.id NOT IN (SELECT comment_id FROM post_comment_block WHERE user_id = ");
+    sql.push_bind(user.id());
+    sql.push(")");
+}
+
+impl SearchHit {
+    /// Posts in the town of the user whose content and place, or one of whose
+    /// comments, contain every bigram of `query`. Posts are ranked by the share
+    /// of the bigrams found in the post and in its best matching comment.
+    /// Blocked authors, posts and comments are left out as in `Post::get`.
+    pub(crate) async fn search(
+        user: &User,
+        query: &str,
+        offset: u32,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let tokens = query_tokens(query);
+        let total = tokens.len() as i64;
+
+        let mut sql = QueryBuilder::<MySql>::new(
+            "SELECT
+p.id,
+p.author_id,
+p.post_type,
+p.town_id,
+p.content,
+p.age_range,
+p.capacity,
+p.place,
+p.starts_at,
+p.ends_at,
+p.timezone,
+p.closed_at,
+(SELECT COUNT(*) FROM post_like as pl WHERE pl.post_id = p.id) as total_likes,
+(SELECT COUNT(*) FROM post_comment as pc WHERE pc.post_id = p.id) as total_comments,
+p.created_at,
+((SELECT COUNT(*) FROM post_search_token as pt WHERE pt.post_id = p.id AND pt.token IN ",
+        );
+        push_tokens(&mut sql, &tokens);
+        sql.push(
+            ") + COALESCE((
+    SELECT COUNT(*) FROM post_comment_search_token as ct
+    INNER JOIN post_comment as pc ON pc.id = ct.comment_id WHERE pc.post_id = p.id AND ",
+        );
+        push_visible_comment(&mut sql, user);
+        sql.push(" AND ct.token IN ");
+        push_tokens(&mut sql, &tokens);
+        sql.push(
+            "
+    GROUP BY ct.comment_id ORDER BY COUNT(*) DESC LIMIT 1
+), 0)) / ",
+        );
+        // A double, so that the score is not decoded as a decimal
+        sql.push_bind(total as f64);
+        sql.push(
+            " as score
+FROM post as p WHERE
+p.author_id NOT IN (SELECT id FROM user WHERE deleted = TRUE) AND
+p.author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ",
+        );
+        sql.push_bind(user.id());
+        sql.push(") AND p.id NOT IN (SELECT post_id FROM post_block WHERE user_id = ");
+        sql.push_bind(user.id());
+        sql.push(") AND p.town_id = ");
+        sql.push_bind(user.town_id());
+        sql.push(
+            " AND (
+    p.id IN (SELECT pt.post_id FROM post_search_token as pt WHERE pt.token IN ",
+        );
+        push_tokens(&mut sql, &tokens);
+        sql.push(" GROUP BY pt.post_id HAVING COUNT(*) = ");
+        sql.push_bind(total);
+        sql.push(
+            ") OR
+    p.id IN (
+        SELECT pc.post_id FROM post_comment_search_token as ct
+        INNER JOIN post_comment as pc ON pc.id = ct.comment_id WHERE ",
+        );
+        push_visible_comment(&mut sql, user);
+        sql.push(" AND ct.token IN ");
+        push_tokens(&mut sql, &tokens);
+        sql.push(" GROUP BY pc.post_id, ct.comment_id HAVING COUNT(*) = ");
+        sql.push_bind(total);
+        sql.push(
+            "
+    )
+)
+ORDER BY score DESC, p.id DESC LIMIT ",
+        );
+        sql.push_bind(limit);
+        sql.push(" OFFSET ");
+        sql.push_bind(offset);
+
+        Ok(sql.build_query_as::<Self>().persistent(false).fetch_all(db).await?)
+    }
+
+    /// Highlights of the post, followed by its best matching comments.
+    pub(crate) async fn highlights(
+        &self,
+        user: &User,
+        query: &str,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Highlight>> {
+        let terms = terms(query);
+        let mut highlights: Vec<Highlight> = [
+            (HighlightField::Content, Some(self.post.content())),
+            (HighlightField::Place, self.post.place()),
+        ]
+        .into_iter()
+        .filter_map(|(field, text)| Highlight::new(field, None, text?, &terms))
+        .collect();
+
+        let tokens = query_tokens(query);
+        let comments =
+            Comment::search_in_post(self.post.id(), user, &tokens, COMMENT_HIGHLIGHTS, db).await?;
+
+        highlights.extend(comments.iter().filter_map(|comment| {
+            Highlight::new(HighlightField::Comment, Some(comment.id()), comment.content(), &terms)
+        }));
+
+        Ok(highlights)
+    }
+
+    pub(crate) fn post(&self) -> &Post {
+        &self.post
+    }
+
+    pub(crate) fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+impl Highlight {
+    /// Returns `None` if no term appears in `text`.
+    fn new(
+        field: HighlightField,
+        comment_id: Option<CommentId>,
+        text: &str,
+        terms: &[Vec<char>],
+    ) -> Option<Self> {
+        let chars: Vec<char> = text.chars().collect();
+        let ranges = find_terms(&chars, terms);
+        let start = ranges.first()?[0].saturating_sub(SNIPPET_LEADING_LENGTH);
+        let end = (start + SNIPPET_LENGTH).min(chars.len());
+
+        Some(Self {
+            field,
+            comment_id,
+            snippet: chars[start..end].iter().collect(),
+            ranges: ranges
+                .into_iter()
+                .filter(|range| range[0] < end)
+                .map(|range| [range[0] - start, range[1].min(end) - start])
+                .collect(),
+        })
+    }
+}
+
+/// Lowercases a character for indexing, querying and highlighting alike.
+/// Only the first character of its lowercase is kept, so that a text keeps the
+/// character offsets of its highlights.
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Words of the query long enough to be matched, compared
+/// case-insensitively.
+fn terms(query: &str) -> Vec<Vec<char>> {
+    query
+        .split_whitespace()
+        .filter(|term| term.chars().count() >= QUERY_MIN_LENGTH)
+        .map(|term| term.chars().map(fold_case).collect())
+        .collect()
+}
+
+/// Bigrams of the words of the query long enough to be matched.
+fn query_tokens(query: &str) -> Vec<String> {
+    let terms: Vec<String> = terms(query).into_iter().map(String::from_iter).collect();
+
+    tokens(&terms.join(" "))
+}
+
+/// Character ranges of every term in `chars`, sorted and merged.
+fn find_terms(chars: &[char], terms: &[Vec<char>]) -> Vec<[usize; 2]> {
+    let mut ranges: Vec<[usize; 2]> = vec![];
+
+    for start in 0..chars.len() {
+        for term in terms {
+            let end = start + term.len();
+            let matched = end <= chars.len()
+                && chars[start..end].iter().zip(term).all(|(c, t)| fold_case(*c) == *t);
+
+            if !matched {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some(last) if last[1] >= start => last[1] = last[1].max(end),
+                _ => ranges.push([start, end]),
+            }
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn tokens_are_lowercased_bigrams_of_each_word() {
+        assert_eq!(tokens("맛집을 추천"), ["맛집", "집을", "추천"]);
+        assert_eq!(tokens("Seoul SE"), ["eo", "ou", "se", "ul"]);
+        assert!(tokens("a 밥").is_empty());
+    }
+
+    #[test]
+    fn case_is_folded_beyond_ascii() {
+        assert_eq!(tokens("ÄÖ"), ["äö"]);
+        assert_eq!(query_tokens("ÄÖ"), tokens("äö"));
+        assert_eq!(find_terms(&chars("ÄPFEL äpfel"), &terms("Äpfel")), [[0, 5], [6, 11]]);
+    }
+
+    #[test]
+    fn folding_case_keeps_character_offsets() {
+        // The lowercase of `İ` is two characters
+        assert_eq!(tokens("İs"), ["is"]);
+        assert_eq!(find_terms(&chars("İstanbul"), &terms("istanbul")), [[0, 8]]);
+    }
+
+    #[test]
+    fn query_tokens_leave_out_short_words() {
+        assert_eq!(query_tokens("a 맛집"), ["맛집"]);
+        assert!(validate_query(" a 밥 ").is_err());
+        assert_eq!(validate_query(" 맛집 ").unwrap(), "맛집");
+    }
+
+    #[test]
+    fn terms_are_found_by_character_offsets() {
+        let terms = terms("맛집");
+
+        assert_eq!(find_terms(&chars("서울 맛집 추천, 맛집"), &terms), [[3, 5], [10, 12]]);
+    }
+
+    #[test]
+    fn overlapping_terms_are_merged() {
+        let terms = terms("ab bcd Hello");
+
+        assert_eq!(find_terms(&chars("xabcdx"), &terms), [[1, 5]]);
+        assert_eq!(find_terms(&chars("HELLO hello"), &terms), [[0, 5], [6, 11]]);
+    }
+
+    #[test]
+    fn highlight_snippet_starts_before_the_first_match() {
+        let text = format!("{}맛집{}", "가".repeat(40), "나".repeat(100));
+        let highlight =
+            Highlight::new(HighlightField::Content, None, &text, &terms("맛집")).unwrap();
+
+        assert_eq!(highlight.snippet.chars().count(), SNIPPET_LENGTH);
+        assert!(highlight.snippet.starts_with(&"가".repeat(SNIPPET_LEADING_LENGTH)));
+        assert_eq!(highlight.ranges, [[SNIPPET_LEADING_LENGTH, SNIPPET_LEADING_LENGTH + 2]]);
+    }
+
+    #[test]
+    fn highlight_ranges_are_cut_at_the_end_of_the_snippet() {
+        let text = format!("맛집{}맛집", "가".repeat(SNIPPET_LENGTH - 3));
+        let highlight =
+            Highlight::new(HighlightField::Content, None, &text, &terms("맛집")).unwrap();
+
+        assert_eq!(highlight.ranges, [[0, 2], [SNIPPET_LENGTH - 1, SNIPPET_LENGTH]]);
+    }
+
+    #[test]
+    fn highlight_needs_a_match() {
+        assert!(Highlight::new(HighlightField::Place, None, "강남역", &terms("맛집")).is_none());
+    }
+}
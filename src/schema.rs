@@ -9,15 +9,22 @@ use tempfile::NamedTempFile;
 use crate::{
     post::{
         comment::{Comment, CommentId, CommentNode},
-        GatheringAgeRange, Post, PostId, PostType,
+        gathering::{GatheringFilter, Participant, ParticipantId},
+        search::Highlight,
+        GatheringAgeRange, Post, PostId, PostImage, PostImageId, PostType,
     },
     town::{Town, TownId},
     user::{
         self,
         account::{User, UserId, VerificationResult},
+        block::{BlockId, CommentBlock, PostBlock, UserBlock},
+        like::{LikeId, PostLike},
+        phone::PhoneNumber,
+        profile::{self, ProfileUpdate},
+        role::Role,
         IdVerificationType,
     },
-    Result,
+    Error, Result,
 };
 
 #[derive(TryFromMultipart)]
@@ -26,18 +33,24 @@ pub struct RegistrationSchema {
     pub name: String,
     pub birthdate: String,
     pub sex: user::Sex,
-    pub phone: String,
+    pub phone: PhoneNumber,
     pub address: String,
 }
 
 #[derive(TryFromMultipart)]
 pub struct PhoneVerificationSetupSchema {
-    pub phone: String,
+    pub phone: PhoneNumber,
 }
 
 #[derive(TryFromMultipart)]
 pub struct PhoneVerificationSchema {
-    pub phone: String,
+    pub phone: PhoneNumber,
+    pub code: String,
+}
+
+#[derive(TryFromMultipart)]
+pub struct PhoneUpdateSchema {
+    pub phone: PhoneNumber,
     pub code: String,
 }
 
@@ -45,32 +58,43 @@ pub struct PhoneVerificationSchema {
 pub struct UserSchema {
     pub id: UserId,
     pub name: String,
-    pub phone: String,
+    pub phone: PhoneNumber,
     pub birthdate: NaiveDate,
     pub sex: String,
     pub town: Town,
+    pub role: Role,
     pub verification_result: VerificationResult,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub verification_type: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub verification_picture_url: Option<String>,
     pub picture: String,
+    pub picture_thumbnails: PictureThumbnails,
     pub bio: String,
     pub total_likes: i64,
 }
 
+#[derive(Serialize)]
+pub struct PictureThumbnails {
+    pub medium: String,
+    pub small: String,
+}
+
 #[derive(Serialize)]
 pub struct OtherUserSchema {
     pub id: UserId,
     pub name: String,
-    pub phone: String,
-    pub birthdate: NaiveDate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<PhoneNumber>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub birthdate: Option<NaiveDate>,
+    pub age_band: u32,
     pub sex: String,
     pub town: Town,
     pub verification_result: VerificationResult,
     pub picture: String,
+    pub picture_thumbnails: PictureThumbnails,
     pub bio: String,
-    pub total_likes: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_likes: Option<i64>,
     pub my_like: bool,
 }
 
@@ -81,17 +105,81 @@ pub struct TokenSchema {
     pub refresh_token: String,
 }
 
+#[derive(Deserialize)]
+pub struct LogoutSchema {
+    pub all: Option<bool>,
+}
+
+impl LogoutSchema {
+    pub fn all(&self) -> bool {
+        self.all.unwrap_or(false)
+    }
+}
+
 #[derive(TryFromMultipart)]
 pub struct ProfilePictureUpdateSchema {
     #[form_data(limit = "unlimited")]
     pub picture: FieldData<NamedTempFile>,
 }
 
+#[derive(TryFromMultipart)]
+pub struct TownUpdateSchema {
+    pub address: String,
+}
+
+#[derive(TryFromMultipart)]
+pub struct ProfileUpdateSchema {
+    pub name: Option<String>,
+    pub birthdate: Option<String>,
+    pub sex: Option<user::Sex>,
+    pub bio: Option<String>,
+}
+
+impl ProfileUpdateSchema {
+    /// Checks every field given, failing on the first invalid one.
+    pub fn validate(self) -> Result<ProfileUpdate> {
+        Ok(ProfileUpdate {
+            name: self.name.as_deref().map(profile::validate_name).transpose()?,
+            birthdate: self.birthdate.as_deref().map(profile::validate_birthdate).transpose()?,
+            sex: self.sex,
+            bio: self.bio.as_deref().map(profile::validate_bio).transpose()?,
+        })
+    }
+}
+
 #[derive(TryFromMultipart)]
 pub struct ProfileBioUpdateSchema {
     pub bio: String,
 }
 
+#[derive(TryFromMultipart)]
+pub struct PrivacySettingsUpdateSchema {
+    pub show_birthdate: Option<bool>,
+    pub show_phone: Option<bool>,
+    pub show_total_likes: Option<bool>,
+}
+
+#[derive(TryFromMultipart)]
+pub struct VerificationReviewSchema {
+    pub result: VerificationResult,
+}
+
+#[derive(Deserialize)]
+pub struct VerificationQueueSchema {
+    pub limit: Option<i32>,
+}
+
+impl VerificationQueueSchema {
+    pub fn limit(&self) -> i32 {
+        self.limit.unwrap_or(20)
+    }
+}
+
+#[derive(TryFromMultipart)]
+pub struct RoleUpdateSchema {
+    pub role: Role,
+}
+
 #[derive(TryFromMultipart)]
 pub struct PostCreationSchema {
     pub post_type: PostType,
@@ -99,6 +187,10 @@ pub struct PostCreationSchema {
     pub age_range: Option<String>,
     pub capacity: Option<u32>,
     pub place: Option<String>,
+    /// RFC 3339, or local time in `timezone`
+    pub starts_at: Option<String>,
+    pub ends_at: Option<String>,
+    pub timezone: Option<String>,
     #[form_data(limit = "unlimited")]
     pub images: Vec<FieldData<NamedTempFile>>,
 }
@@ -108,6 +200,7 @@ pub struct PostAuthor {
     pub id: UserId,
     pub name: String,
     pub picture: String,
+    pub picture_thumbnails: PictureThumbnails,
 }
 
 impl From<User> for PostAuthor {
@@ -116,6 +209,7 @@ impl From<User> for PostAuthor {
             id: value.id(),
             name: value.name().to_string(),
             picture: value.picture().to_string(),
+            picture_thumbnails: value.picture_thumbnails(),
         }
     }
 }
@@ -128,15 +222,28 @@ pub struct PostGetResult {
     pub town_id: TownId,
     pub content: String,
     pub images: Vec<String>,
+    pub image_ids: Vec<PostImageId>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub age_range: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capacity: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub place: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closed_at: Option<DateTime<Utc>>,
     pub total_likes: i64,
     pub my_like: bool,
     pub total_comments: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_participants: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub joined: Option<bool>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -161,6 +268,7 @@ impl PostGetResult {
             post.images(db).await?,
             age_range,
             Self::my_like(user, post, db).await?,
+            Self::participation(user, post, db).await?,
         ))
     }
 
@@ -190,6 +298,7 @@ impl PostGetResult {
                 post.images(db).await?,
                 age_range,
                 Self::my_like(user, post, db).await?,
+                Self::participation(user, post, db).await?,
             ));
         }
 
@@ -199,9 +308,10 @@ impl PostGetResult {
     fn new(
         post: &Post,
         user: User,
-        images: Vec<String>,
+        images: Vec<PostImage>,
         age_range: Option<String>,
         my_like: bool,
+        participation: Option<(i64, bool)>,
     ) -> Self {
         Self {
             id: post.id(),
@@ -209,13 +319,20 @@ impl PostGetResult {
             post_type: post.post_type(),
             town_id: post.town_id(),
             content: post.content().to_string(),
-            images,
+            images: images.iter().map(|image| image.url().to_string()).collect(),
+            image_ids: images.iter().map(PostImage::id).collect(),
             age_range,
             capacity: post.capacity(),
             place: post.place().map(str::to_string),
+            starts_at: post.starts_at(),
+            ends_at: post.ends_at(),
+            timezone: post.timezone().map(str::to_string),
+            closed_at: post.closed_at(),
             total_likes: post.total_likes(),
             my_like,
             total_comments: post.total_comments(),
+            total_participants: participation.map(|(total, _)| total),
+            joined: participation.map(|(_, joined)| joined),
             created_at: post.created_at(),
         }
     }
@@ -230,11 +347,40 @@ impl PostGetResult {
         .await?
         .is_some())
     }
+
+    /// Number of participants and whether `user` is one of them, for
+    /// gatherings only.
+    async fn participation(
+        user: &User,
+        post: &Post,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Option<(i64, bool)>> {
+        if !matches!(post.post_type(), PostType::Gathering) {
+            return Ok(None);
+        }
+
+        Ok(Some((
+            Participant::count(post.id(), db).await?,
+            Participant::exists(post.id(), user.id(), db).await?,
+        )))
+    }
 }
 
+/// Fields left out are not changed.
 #[derive(TryFromMultipart)]
 pub struct PostEditSchema {
-    pub content: String,
+    pub post_type: Option<PostType>,
+    pub content: Option<String>,
+    pub age_range: Option<String>,
+    pub capacity: Option<u32>,
+    pub place: Option<String>,
+    pub starts_at: Option<String>,
+    pub ends_at: Option<String>,
+    pub timezone: Option<String>,
+    /// Comma-separated ids of the images to keep, in the order to show them.
+    /// Left out, every image is kept; empty, every image is removed.
+    pub image_ids: Option<String>,
+    /// Added after the kept images
     #[form_data(limit = "unlimited")]
     pub images: Vec<FieldData<NamedTempFile>>,
 }
@@ -264,16 +410,20 @@ pub struct CommentGetResult {
 
 impl CommentGetResult {
     pub(crate) async fn from_comment(comment: Comment, db: &sqlx::Pool<MySql>) -> Result<Self> {
-        let author = if let Some(author_id) = comment.author_id() {
-            Some(User::from_id(author_id, db).await?)
-        } else {
-            None
+        // Comments of deleted accounts are shown without their author
+        let author = match comment.author_id() {
+            Some(author_id) => match User::from_id(author_id, db).await {
+                Ok(author) => Some(author.into()),
+                Err(Error::DeletedUser) => None,
+                Err(err) => return Err(err),
+            },
+            None => None,
         };
 
         Ok(Self {
             id: comment.id(),
             post_id: comment.post_id(),
-            author: author.map(Into::into),
+            author,
             content: {
                 if !comment.is_deleted() {
                     Some(comment.content().to_string())
@@ -324,6 +474,14 @@ pub(crate) struct CommentResultNode {
 pub struct PostListSchema {
     pub last_id: Option<PostId>,
     pub limit: Option<i32>,
+    pub post_type: Option<PostType>,
+    pub author_id: Option<UserId>,
+    /// Inclusive lower bound of the creation time
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive upper bound of the creation time
+    pub until: Option<DateTime<Utc>>,
+    pub has_images: Option<bool>,
+    pub gathering: Option<GatheringFilter>,
 }
 
 impl PostListSchema {
@@ -336,6 +494,219 @@ impl PostListSchema {
     }
 }
 
+/// Offset-based pagination, as search results are ordered by relevance.
+#[derive(Deserialize)]
+pub struct PostSearchSchema {
+    pub q: String,
+    pub offset: Option<u32>,
+    pub limit: Option<i32>,
+}
+
+impl PostSearchSchema {
+    pub fn offset(&self) -> u32 {
+        self.offset.unwrap_or(0)
+    }
+
+    pub fn limit(&self) -> i32 {
+        self.limit.unwrap_or(10)
+    }
+}
+
+#[derive(Serialize)]
+pub struct PostSearchResult {
+    #[serde(flatten)]
+    pub post: PostGetResult,
+    pub score: f64,
+    pub highlights: Vec<Highlight>,
+}
+
+/// Cursor-based pagination over rows with an auto-increment id, newest first.
+#[derive(Deserialize)]
+pub struct ListSchema {
+    pub last_id: Option<u64>,
+    pub limit: Option<i32>,
+}
+
+impl ListSchema {
+    pub fn last_id(&self) -> u64 {
+        self.last_id.unwrap_or(u64::MAX)
+    }
+
+    pub fn limit(&self) -> i32 {
+        self.limit.unwrap_or(10)
+    }
+}
+
+#[derive(Serialize)]
+pub struct UserLikeListItem {
+    pub like_id: LikeId,
+    pub user: PostAuthor,
+}
+
+impl UserLikeListItem {
+    /// Lists the users of likes on users or posts, given as their like and
+    /// user ids.
+    pub(crate) async fn from_likes(
+        likes: Vec<(LikeId, UserId)>,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut results: Vec<Self> = Vec::with_capacity(likes.len());
+
+        for (like_id, user_id) in likes {
+            let user = User::from_id(user_id, db).await?;
+
+            results.push(Self { like_id, user: user.into() });
+        }
+
+        Ok(results)
+    }
+}
+
+/// A like on a post of the user, with who left it.
+#[derive(Serialize)]
+pub struct ReceivedPostLikeItem {
+    pub post_id: PostId,
+    #[serde(flatten)]
+    pub like: UserLikeListItem,
+}
+
+#[derive(Serialize)]
+pub struct PostLikeListItem {
+    pub like_id: LikeId,
+    pub post: PostGetResult,
+}
+
+impl PostLikeListItem {
+    pub(crate) async fn from_likes(
+        likes: Vec<PostLike>,
+        user: &User,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut results: Vec<Self> = Vec::with_capacity(likes.len());
+
+        for like in likes {
+            // Liked posts stay listed even after the user moves to another town
+            let post = Post::from_id_unscoped(like.post_id(), db).await?;
+
+            results.push(Self {
+                like_id: like.id(),
+                post: PostGetResult::from_post(&post, user, db).await?,
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+/// Number of characters of blocked content shown in the block lists
+const BLOCK_PREVIEW_LENGTH: usize = 100;
+
+fn preview(content: &str) -> String {
+    content.chars().take(BLOCK_PREVIEW_LENGTH).collect()
+}
+
+#[derive(Serialize)]
+pub struct BlockedUserItem {
+    pub block_id: BlockId,
+    pub user: PostAuthor,
+}
+
+impl BlockedUserItem {
+    pub(crate) async fn from_blocks(
+        blocks: Vec<UserBlock>,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut results: Vec<Self> = Vec::with_capacity(blocks.len());
+
+        for block in blocks {
+            let user = User::from_id(block.target_id(), db).await?;
+
+            results.push(Self { block_id: block.id(), user: user.into() });
+        }
+
+        Ok(results)
+    }
+}
+
+#[derive(Serialize)]
+pub struct BlockedPostItem {
+    pub block_id: BlockId,
+    pub post_id: PostId,
+    pub author: PostAuthor,
+    pub post_type: PostType,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl BlockedPostItem {
+    pub(crate) async fn from_blocks(
+        blocks: Vec<PostBlock>,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut results: Vec<Self> = Vec::with_capacity(blocks.len());
+
+        for block in blocks {
+            let post = Post::from_id_unscoped(block.post_id(), db).await?;
+            let author = User::from_id(post.author_id(), db).await?;
+
+            results.push(Self {
+                block_id: block.id(),
+                post_id: post.id(),
+                author: author.into(),
+                post_type: post.post_type(),
+                content: preview(post.content()),
+                created_at: post.created_at(),
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+#[derive(Serialize)]
+pub struct BlockedCommentItem {
+    pub block_id: BlockId,
+    pub comment_id: CommentId,
+    pub post_id: PostId,
+    pub author: Option<PostAuthor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl BlockedCommentItem {
+    pub(crate) async fn from_blocks(
+        blocks: Vec<CommentBlock>,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut results: Vec<Self> = Vec::with_capacity(blocks.len());
+
+        for block in blocks {
+            let comment = Comment::from_id_ignore_block(block.comment_id(), db).await?;
+            // The comment can still be unblocked after its author deleted the account
+            let author = match comment.author_id() {
+                Some(author_id) => match User::from_id(author_id, db).await {
+                    Ok(author) => Some(author.into()),
+                    Err(Error::DeletedUser) => None,
+                    Err(err) => return Err(err),
+                },
+                None => None,
+            };
+
+            results.push(Self {
+                block_id: block.id(),
+                comment_id: comment.id(),
+                post_id: comment.post_id(),
+                author,
+                content: (!comment.is_deleted()).then(|| preview(comment.content())),
+                created_at: comment.created_at(),
+            });
+        }
+
+        Ok(results)
+    }
+}
+
 #[derive(Serialize)]
 pub struct UserLikeResult {
     pub issuer_id: UserId,
@@ -348,6 +719,55 @@ pub struct PostLikeResult {
     pub post_id: UserId,
 }
 
+#[derive(Serialize)]
+pub struct ParticipationResult {
+    pub user_id: UserId,
+    pub post_id: PostId,
+    pub total_participants: i64,
+}
+
+#[derive(Serialize)]
+pub struct ParticipantListItem {
+    pub participant_id: ParticipantId,
+    pub user: PostAuthor,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl ParticipantListItem {
+    pub(crate) async fn from_participants(
+        participants: Vec<Participant>,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        let mut results: Vec<Self> = Vec::with_capacity(participants.len());
+
+        for participant in participants {
+            let user = User::from_id(participant.user_id(), db).await?;
+
+            results.push(Self {
+                participant_id: participant.id(),
+                user: user.into(),
+                joined_at: participant.created_at(),
+            });
+        }
+
+        Ok(results)
+    }
+}
+
+/// A gathering joined by the user, for the data export.
+#[derive(Serialize)]
+pub struct JoinedGatheringItem {
+    pub participant_id: ParticipantId,
+    pub post_id: PostId,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl From<Participant> for JoinedGatheringItem {
+    fn from(value: Participant) -> Self {
+        Self { participant_id: value.id(), post_id: value.post_id(), joined_at: value.created_at() }
+    }
+}
+
 #[derive(TryFromMultipart)]
 pub struct UserVerification {
     pub verification_type: IdVerificationType,
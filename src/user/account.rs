@@ -1,31 +1,43 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
-use std::{fs::File, path::PathBuf};
-
-use axum_typed_multipart::FieldData;
+use axum_typed_multipart::{FieldData, TryFromField};
 use chrono::{DateTime, NaiveDate, Utc};
 use rand::{distributions::Alphanumeric, Rng};
 use serde_repr::Serialize_repr;
 use sqlx::MySql;
 use tempfile::NamedTempFile;
-use tokio::{fs, io};
+use tracing::warn;
 
 use crate::{
     aws,
+    media::{ImageVariant, ProcessedImage},
     post::{comment::Comment, Post},
-    schema::{OtherUserSchema, RegistrationSchema, UserSchema},
+    schema::{OtherUserSchema, PictureThumbnails, RegistrationSchema, UserSchema},
     town::{Town, TownId},
     Error, Result,
 };
 
-use super::{IdVerificationType, Sex};
+use super::{
+    deletion::{AccountDeletion, DeletionStep},
+    history::{AccountEvent, AccountHistory},
+    phone::PhoneNumber,
+    privacy::{age_band, PrivacySettings},
+    profile::{self, ProfileUpdate},
+    role::Role,
+    verification::Verification,
+    IdVerificationType, Sex,
+};
 
 pub(crate) type UserId = u64;
 
-const VERIFICATION_PHOTO_PATH: &str = "verification_photo/";
+const PROFILE_PICTURE_PATH: &str = "profile_image/";
+
+/// Key of the picture set by default on `user.picture`, shared by every account
+pub(crate) const DEFAULT_PICTURE_KEY: &str = "profile_image/profile_image_default.png";
 
-#[derive(Debug, sqlx::Type, Clone, Copy, Serialize_repr)]
+#[derive(Debug, TryFromField, sqlx::Type, Clone, Copy, Serialize_repr)]
 #[repr(u32)]
+#[try_from_field(rename_all = "snake_case")]
 pub enum VerificationResult {
     NotVerified = 0,
     Verified = 1,
@@ -41,18 +53,20 @@ pub enum VerificationResult {
 pub(crate) struct User {
     id: UserId,
     name: String,
-    phone: String,
+    phone: PhoneNumber,
     birthdate: NaiveDate,
     sex: Sex,
     town_id: TownId,
+    role: Role,
     verification_result: VerificationResult,
     verification_type: Option<IdVerificationType>,
-    verification_picture_url: Option<String>,
     picture: String,
+    picture_medium: Option<String>,
+    picture_small: Option<String>,
     bio: Option<String>,
     deleted: bool,
-    refresh_token: Option<String>,
     total_likes: i64,
+    #[allow(dead_code)]
     created_at: DateTime<Utc>,
     #[allow(dead_code)]
     updated_at: DateTime<Utc>,
@@ -62,6 +76,8 @@ impl User {
     pub(crate) async fn register(data: RegistrationSchema, db: &sqlx::Pool<MySql>) -> Result<Self> {
         let tx = db.begin().await?;
 
+        let name = profile::validate_name(&data.name)?;
+        let birthdate = profile::validate_birthdate(&data.birthdate)?;
         let town_id = Town::from_address(&data.address, db).await.map(|town| town.id())?;
         let user_id = sqlx::query!(
             "INSERT INTO user (
@@ -76,9 +92,9 @@ town_id) VALUES (
 ?,
 ?
 )",
-            data.name,
+            name,
             data.phone,
-            data.birthdate,
+            birthdate,
             data.sex,
             town_id,
         )
@@ -99,17 +115,20 @@ town_id) VALUES (
             "SELECT
 id,
 name,
-phone,
+phone as `phone: PhoneNumber`,
 birthdate,
 sex as `sex: Sex`,
 town_id,
-verification_result as `verification_result: _`,
-verification_type as `verification_type: _`,
-verification_picture_url,
+role as `role: Role`,
+COALESCE((SELECT IF(revoked_at IS NULL, result, 0) FROM user_verification as uv
+WHERE uv.user_id = u.id ORDER BY uv.id DESC LIMIT 1), 0) as `verification_result!: VerificationResult`,
+(SELECT verification_type FROM user_verification as uv
+WHERE uv.user_id = u.id ORDER BY uv.id DESC LIMIT 1) as `verification_type: IdVerificationType`,
 picture,
+picture_medium,
+picture_small,
 bio,
 deleted as `deleted: _`,
-refresh_token,
 (SELECT COUNT(*) FROM user_like as ul WHERE ul.target_id = u.id) as `total_likes!`,
 created_at,
 updated_at
@@ -128,23 +147,26 @@ FROM user as u WHERE u.id = ?",
         })
     }
 
-    pub(crate) async fn from_phone(phone: &str, db: &sqlx::Pool<MySql>) -> Result<Self> {
+    pub(crate) async fn from_phone(phone: &PhoneNumber, db: &sqlx::Pool<MySql>) -> Result<Self> {
         sqlx::query_as!(
             Self,
             "SELECT
 id,
 name,
-phone,
+phone as `phone: PhoneNumber`,
 birthdate,
 sex as `sex: Sex`,
 town_id,
-verification_result as `verification_result: _`,
-verification_type as `verification_type: _`,
-verification_picture_url,
+role as `role: Role`,
+COALESCE((SELECT IF(revoked_at IS NULL, result, 0) FROM user_verification as uv
+WHERE uv.user_id = u.id ORDER BY uv.id DESC LIMIT 1), 0) as `verification_result!: VerificationResult`,
+(SELECT verification_type FROM user_verification as uv
+WHERE uv.user_id = u.id ORDER BY uv.id DESC LIMIT 1) as `verification_type: IdVerificationType`,
 picture,
+picture_medium,
+picture_small,
 bio,
 deleted as `deleted: _`,
-refresh_token,
 (SELECT COUNT(*) FROM user_like as ul WHERE ul.target_id = u.id) as `total_likes!`,
 created_at,
 updated_at
@@ -173,10 +195,11 @@ FROM user as u WHERE phone = ?",
             birthdate: self.birthdate,
             sex: self.sex.to_string(),
             town,
+            role: self.role,
             verification_result: self.verification_result,
             verification_type: self.verification_type.map(|value| value.to_string()),
-            verification_picture_url: self.verification_picture_url.clone(),
             picture: self.picture.clone(),
+            picture_thumbnails: self.picture_thumbnails(),
             bio: self.bio.clone().unwrap_or_default(),
             total_likes: self.total_likes,
         })
@@ -192,6 +215,7 @@ FROM user as u WHERE phone = ?",
         }
 
         let town = Town::from_id(self.town_id, db).await?;
+        let privacy = PrivacySettings::from_user_id(self.id, db).await?;
         let my_like = sqlx::query!(
             "SELECT id FROM user_like WHERE issuer_id = ? AND target_id = ? LIMIT 1",
             requester.id,
@@ -204,74 +228,259 @@ FROM user as u WHERE phone = ?",
         Ok(OtherUserSchema {
             id: self.id,
             name: self.name.clone(),
-            phone: self.phone.clone(),
-            birthdate: self.birthdate,
+            phone: privacy.show_phone().then(|| self.phone.clone()),
+            birthdate: privacy.show_birthdate().then_some(self.birthdate),
+            age_band: age_band(self.birthdate),
             sex: self.sex.to_string(),
             town,
             verification_result: self.verification_result,
             picture: self.picture.clone(),
+            picture_thumbnails: self.picture_thumbnails(),
             bio: self.bio.clone().unwrap_or_default(),
-            total_likes: self.total_likes,
+            total_likes: privacy.show_total_likes().then_some(self.total_likes),
             my_like,
         })
     }
 
-    pub(crate) async fn update_refresh_token(
-        &self,
-        token: &str,
+    /// Moves the account to another phone number and records the change.
+    pub(crate) async fn update_phone(
+        &mut self,
+        phone: &PhoneNumber,
         db: &sqlx::Pool<MySql>,
     ) -> Result<()> {
-        sqlx::query!("UPDATE user SET refresh_token = ? WHERE id = ?", token, self.id)
-            .execute(db)
-            .await?;
+        if &self.phone == phone {
+            return Err(Error::InvalidRequest);
+        }
+
+        let mut tx = db.begin().await?;
+
+        sqlx::query!("UPDATE user SET phone = ? WHERE id = ?", phone, self.id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+                    Error::PhoneAlreadyInUse(phone.to_string())
+                }
+                _ => Error::Database(err),
+            })?;
+        AccountHistory::record(
+            &mut *tx,
+            self.id,
+            AccountEvent::PhoneChange,
+            Some(self.phone.as_str()),
+            Some(phone.as_str()),
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        self.phone = phone.clone();
 
         Ok(())
     }
 
-    pub(crate) fn verify_refresh_token(&self, refresh_token: &str) -> Result<()> {
-        if refresh_token.is_empty() {
-            return Err(Error::InvalidToken);
+    /// Moves the user to the town of `address`. Posts stay in the town they
+    /// were written in.
+    ///
+    /// The town can be changed once every `interval_days` days, and a verified
+    /// user has to verify again if `requires_verification` is set.
+    pub(crate) async fn update_town(
+        &mut self,
+        address: &str,
+        interval_days: i64,
+        requires_verification: bool,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Town> {
+        let town = Town::from_address(address, db).await?;
+
+        if town.id() == self.town_id {
+            return Err(Error::InvalidRequest);
         }
 
-        if let Some(user_token) = &self.refresh_token {
-            if user_token != refresh_token {
-                return Err(Error::InvalidToken);
-            }
-        } else {
-            return Err(Error::InvalidToken);
+        let mut tx = db.begin().await?;
+
+        // Serializes concurrent changes so that the interval cannot be bypassed
+        sqlx::query!("SELECT id FROM user WHERE id = ? FOR UPDATE", self.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let retry_after = sqlx::query_scalar!(
+            "SELECT TIMESTAMPDIFF(SECOND, NOW(), MAX(created_at) + INTERVAL ? DAY)
+FROM account_history WHERE user_id = ? AND event = ?",
+            interval_days,
+            self.id,
+            AccountEvent::TownChange
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let Some(retry_after) = retry_after.filter(|retry_after| *retry_after > 0) {
+            return Err(Error::TownChangeTooSoon { retry_after });
+        }
+
+        sqlx::query!("UPDATE user SET town_id = ? WHERE id = ?", town.id(), self.id)
+            .execute(&mut *tx)
+            .await?;
+        AccountHistory::record(
+            &mut *tx,
+            self.id,
+            AccountEvent::TownChange,
+            Some(&self.town_id.to_string()),
+            Some(&town.id().to_string()),
+        )
+        .await?;
+
+        if requires_verification && self.is_verified() {
+            Verification::revoke(&mut *tx, self.id).await?;
+            self.verification_result = VerificationResult::NotVerified;
         }
 
+        tx.commit().await?;
+
+        self.town_id = town.id();
+
+        Ok(town)
+    }
+
+    pub(crate) async fn update_role(&mut self, role: Role, db: &sqlx::Pool<MySql>) -> Result<()> {
+        sqlx::query!("UPDATE user SET role = ? WHERE id = ?", role, self.id).execute(db).await?;
+
+        self.role = role;
+
         Ok(())
     }
 
     pub(crate) async fn update_bio(&mut self, bio: &str, db: &sqlx::Pool<MySql>) -> Result<()> {
+        let bio = profile::validate_bio(bio)?;
+
         Ok(sqlx::query!("UPDATE user SET bio = ? WHERE id = ?", bio, self.id)
             .execute(db)
             .await
             .map(|_| {
-                self.bio = Some(bio.to_string());
+                self.bio = Some(bio);
             })?)
     }
 
+    /// Updates the given profile fields. The name and the birthdate are
+    /// confirmed by the identity verification, so they are locked afterwards.
+    pub(crate) async fn update_profile(
+        &mut self,
+        update: ProfileUpdate,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
+        if self.is_verified() {
+            let locked = |field| Error::InvalidField {
+                field,
+                reason: String::from("cannot be changed after the identity verification"),
+            };
+
+            if update.name.as_ref().is_some_and(|name| name != &self.name) {
+                return Err(locked("name"));
+            }
+            if update.birthdate.is_some_and(|birthdate| birthdate != self.birthdate) {
+                return Err(locked("birthdate"));
+            }
+        }
+
+        sqlx::query!(
+            "UPDATE user SET
+name = COALESCE(?, name),
+birthdate = COALESCE(?, birthdate),
+sex = COALESCE(?, sex),
+bio = COALESCE(?, bio)
+WHERE id = ?",
+            update.name,
+            update.birthdate,
+            update.sex,
+            update.bio,
+            self.id
+        )
+        .execute(db)
+        .await?;
+
+        if let Some(name) = update.name {
+            self.name = name;
+        }
+        if let Some(birthdate) = update.birthdate {
+            self.birthdate = birthdate;
+        }
+        if let Some(sex) = update.sex {
+            self.sex = sex;
+        }
+        if let Some(bio) = update.bio {
+            self.bio = Some(bio);
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn update_picture(
         &mut self,
         picture: FieldData<NamedTempFile>,
         s3: &aws::S3Client,
         db: &sqlx::Pool<MySql>,
     ) -> Result<String> {
-        let picture_path = PicturePath::generate(self.id).await?;
+        let image = ProcessedImage::from_file(picture.contents.path()).await?;
+        // A new key for every upload keeps caches from serving the previous picture
+        let version: String =
+            rand::thread_rng().sample_iter(Alphanumeric).take(16).map(char::from).collect();
+        let key = |variant: ImageVariant| {
+            format!("{PROFILE_PICTURE_PATH}{}/{version}{}", self.id, variant.key_suffix())
+        };
+
+        let picture_url =
+            s3.push_file(image.path(ImageVariant::Full), &key(ImageVariant::Full)).await?;
+        let picture_medium =
+            s3.push_file(image.path(ImageVariant::Medium), &key(ImageVariant::Medium)).await?;
+        let picture_small =
+            s3.push_file(image.path(ImageVariant::Small), &key(ImageVariant::Small)).await?;
 
-        picture.contents.persist(&picture_path.file_path).map_err(|err| Error::PersistFile {
-            path: picture_path.file_path.to_path_buf(),
-            source: err.into(),
-        })?;
+        sqlx::query!(
+            "UPDATE user SET picture = ?, picture_medium = ?, picture_small = ? WHERE id = ?",
+            picture_url,
+            picture_medium,
+            picture_small,
+            self.id
+        )
+        .execute(db)
+        .await?;
 
-        let picture_url = s3.push_file(&picture_path.file_path, &picture_path.upload_path).await?;
+        let previous_pictures = self.picture_urls();
 
-        sqlx::query!("UPDATE user SET picture = ? WHERE id = ?", picture_url, self.id)
-            .execute(db)
+        self.picture = picture_url.clone();
+        self.picture_medium = Some(picture_medium);
+        self.picture_small = Some(picture_small);
+
+        Self::delete_picture_files(&previous_pictures, s3).await;
+
+        Ok(picture_url)
+    }
+
+    /// Restores the default profile picture.
+    pub(crate) async fn reset_picture(
+        &mut self,
+        s3: &aws::S3Client,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<String> {
+        sqlx::query!(
+            "UPDATE user SET picture = DEFAULT, picture_medium = NULL, picture_small = NULL
+WHERE id = ?",
+            self.id
+        )
+        .execute(db)
+        .await?;
+        let picture_url = sqlx::query_scalar!("SELECT picture FROM user WHERE id = ?", self.id)
+            .fetch_one(db)
             .await?;
 
+        let previous_pictures = self.picture_urls();
+
+        self.picture = picture_url.clone();
+        self.picture_medium = None;
+        self.picture_small = None;
+
+        Self::delete_picture_files(&previous_pictures, s3).await;
+
         Ok(picture_url)
     }
 
@@ -328,7 +537,7 @@ FROM user as u WHERE phone = ?",
 
     pub(crate) async fn block_user(&self, target: &User, db: &sqlx::Pool<MySql>) -> Result<()> {
         sqlx::query!(
-            "INSERT INTO user_block (user_id, target_id) VALUES (?, ?)",
+            "INSERT IGNORE INTO user_block (user_id, target_id) VALUES (?, ?)",
             self.id,
             target.id
         )
@@ -351,9 +560,13 @@ FROM user as u WHERE phone = ?",
     }
 
     pub(crate) async fn block_post(&self, post: &Post, db: &sqlx::Pool<MySql>) -> Result<()> {
-        sqlx::query!("INSERT INTO post_block (user_id, post_id) VALUES (?, ?)", self.id, post.id())
-            .execute(db)
-            .await?;
+        sqlx::query!(
+            "INSERT IGNORE INTO post_block (user_id, post_id) VALUES (?, ?)",
+            self.id,
+            post.id()
+        )
+        .execute(db)
+        .await?;
 
         Ok(())
     }
@@ -376,7 +589,7 @@ FROM user as u WHERE phone = ?",
         db: &sqlx::Pool<MySql>,
     ) -> Result<()> {
         sqlx::query!(
-            "INSERT INTO post_comment_block (user_id, comment_id) VALUES (?, ?)",
+            "INSERT IGNORE INTO post_comment_block (user_id, comment_id) VALUES (?, ?)",
             self.id,
             comment.id()
         )
@@ -402,51 +615,48 @@ FROM user as u WHERE phone = ?",
         Ok(())
     }
 
-    pub(crate) async fn update_verification(
-        &mut self,
-        verification_type: IdVerificationType,
-        picture: FieldData<NamedTempFile<File>>,
-        db: &sqlx::Pool<MySql>,
-        s3: &aws::S3Client,
-    ) -> Result<String> {
-        if self.is_verified() {
-            return Err(Error::InvalidRequest);
-        }
-
-        self.delete_verification_picture(s3).await?;
+    /// Marks the account as deleted. The account is purged by
+    /// `AccountDeletion` once the grace period has passed.
+    pub(crate) async fn treat_as_deleted(self, db: &sqlx::Pool<MySql>) -> Result<()> {
+        let mut tx = db.begin().await?;
 
-        sqlx::query!(
-            "UPDATE user SET verification_result = ?, verification_picture_url = NULL WHERE id = ?",
-            VerificationResult::NotVerified,
-            self.id
-        )
-        .execute(db)
-        .await?;
+        sqlx::query!("UPDATE user SET deleted = TRUE, deleted_at = NOW() WHERE id = ?", self.id)
+            .execute(&mut *tx)
+            .await?;
+        AccountDeletion::log(&mut *tx, self.id, DeletionStep::Requested, None).await?;
 
-        self.verification_result = VerificationResult::NotVerified;
-        self.verification_picture_url = None;
+        tx.commit().await?;
 
-        let url = Self::upload_verification_picture(picture, s3).await?;
+        Ok(())
+    }
 
-        sqlx::query!(
-            "UPDATE user SET verification_type = ?, verification_picture_url = ? WHERE id = ?",
-            verification_type,
-            url,
-            self.id
+    /// Restores an account deleted within the last `grace_period_days` days.
+    pub(crate) async fn restore(
+        phone: &PhoneNumber,
+        grace_period_days: i64,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Self> {
+        let mut tx = db.begin().await?;
+
+        let id = sqlx::query_scalar!(
+            "SELECT id as `id: UserId` FROM user
+WHERE phone = ? AND deleted = TRUE AND deleted_at >= NOW() - INTERVAL ? DAY
+FOR UPDATE",
+            phone,
+            grace_period_days
         )
-        .execute(db)
-        .await?;
-
-        self.verification_type = Some(verification_type);
-        self.verification_picture_url = Some(url.clone());
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::DeletedUser)?;
 
-        Ok(url)
-    }
+        sqlx::query!("UPDATE user SET deleted = FALSE, deleted_at = NULL WHERE id = ?", id)
+            .execute(&mut *tx)
+            .await?;
+        AccountDeletion::log(&mut *tx, id, DeletionStep::Restored, None).await?;
 
-    pub(crate) async fn treat_as_deleted(self, db: &sqlx::Pool<MySql>) -> Result<()> {
-        sqlx::query!("UPDATE user SET deleted = TRUE WHERE id = ?", self.id).execute(db).await?;
+        tx.commit().await?;
 
-        Ok(())
+        Self::from_id(id, db).await
     }
 
     pub(crate) fn id(&self) -> UserId {
@@ -461,73 +671,54 @@ FROM user as u WHERE phone = ?",
         self.town_id
     }
 
+    pub(crate) fn role(&self) -> Role {
+        self.role
+    }
+
     pub(crate) fn name(&self) -> &str {
         &self.name
     }
 
-    pub(crate) fn picture(&self) -> &str {
-        &self.picture
+    pub(crate) fn phone(&self) -> &PhoneNumber {
+        &self.phone
     }
 
-    pub(crate) fn created_at(&self) -> DateTime<Utc> {
-        self.created_at
+    pub(crate) fn birthdate(&self) -> NaiveDate {
+        self.birthdate
     }
 
-    async fn upload_verification_picture(
-        photo: FieldData<NamedTempFile<File>>,
-        s3: &aws::S3Client,
-    ) -> Result<String> {
-        let basename: String =
-            rand::thread_rng().sample_iter(Alphanumeric).take(32).map(char::from).collect();
-        let dir = std::env::temp_dir().join(std::env!("CARGO_PKG_NAME"));
-        let temp_path = dir.join(&basename);
-
-        fs::create_dir_all(&dir)
-            .await
-            .map_err(|err| Error::Io { path: dir.to_path_buf(), source: err })?;
-        photo
-            .contents
-            .persist(&temp_path)
-            .map_err(|err| Error::PersistFile { path: temp_path.clone(), source: err.into() })?;
-
-        s3.push_file(&temp_path, &(String::from(VERIFICATION_PHOTO_PATH) + &basename)).await
+    pub(crate) fn picture(&self) -> &str {
+        &self.picture
     }
 
-    async fn delete_verification_picture(&mut self, s3: &aws::S3Client) -> Result<()> {
-        let picture_url = self.verification_picture_url.clone();
-
-        match picture_url {
-            Some(picture_url) if !picture_url.is_empty() => {
-                s3.delete_file(&picture_url).await?;
-
-                self.verification_picture_url = None;
-
-                Ok(())
-            }
-            _ => Ok(()),
+    /// Thumbnails of the profile picture. Pictures uploaded before thumbnails
+    /// were introduced fall back to the full-size picture.
+    pub(crate) fn picture_thumbnails(&self) -> PictureThumbnails {
+        PictureThumbnails {
+            medium: self.picture_medium.clone().unwrap_or_else(|| self.picture.clone()),
+            small: self.picture_small.clone().unwrap_or_else(|| self.picture.clone()),
         }
     }
-}
-
-struct PicturePath {
-    file_path: PathBuf,
-    upload_path: String,
-}
 
-impl PicturePath {
-    async fn generate(user_id: UserId) -> Result<Self> {
-        let temp_dir = std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
-
-        fs::create_dir_all(&temp_dir)
-            .await
-            .or_else(|error| match error.kind() {
-                io::ErrorKind::AlreadyExists => Ok(()),
-                _ => Err(error),
-            })
-            .map_err(|err| Error::Io { path: temp_dir.to_path_buf(), source: err })?;
-
-        let s3_path = format!("profile_image/{}", user_id);
+    fn picture_urls(&self) -> Vec<String> {
+        [Some(&self.picture), self.picture_medium.as_ref(), self.picture_small.as_ref()]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    }
 
-        Ok(PicturePath { file_path: temp_dir.join(user_id.to_string()), upload_path: s3_path })
+    /// Deletes pictures that are no longer referenced. The update has already
+    /// been saved, so a failure only leaves an orphaned file behind.
+    async fn delete_picture_files(urls: &[String], s3: &aws::S3Client) {
+        for key in urls
+            .iter()
+            .filter_map(|url| s3.key_from_url(url))
+            .filter(|key| *key != DEFAULT_PICTURE_KEY)
+        {
+            if let Err(err) = s3.delete_file(key).await {
+                warn!("failed to delete the previous profile picture {key}: {err}");
+            }
+        }
     }
 }
@@ -1,168 +1,203 @@
 // Copyright 2023. The downtown authors all rights reserved.
 
-use chrono::{DateTime, Utc};
-use once_cell::sync;
-use rand::Rng;
-use reqwest::Url;
-use serde::Deserialize;
-use sqlx::MySql;
-
-use crate::{Error, Result};
+use std::net::IpAddr;
 
-static ALIGO_HOST: sync::Lazy<Url> =
-    sync::Lazy::new(|| Url::parse("https://kakaoapi.aligo.in").unwrap());
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use sqlx::{Connection, MySql, MySqlConnection, MySqlExecutor};
 
-const ALIGO_TOKEN_CREATE_PATH: &str = "akv10/token/create/";
-const ALIGO_SEND_PATH: &str = "akv10/alimtalk/send/";
+use crate::{message::MessageProvider, Error, Result};
 
-const ALIGO_TOKEN_LIFETIME_SEC: i32 = 30;
+use super::{phone::PhoneNumber, test_account::TestAccount};
 
-const ALIGO_API_KEY: &str = "5huac2s03ek6bz2k94ysnh1y3nju3laz";
-const ALIGO_USER_ID: &str = "chysaek";
-const ALIGO_SENDER_KEY: &str = "ed4c71c275d2cf3a12a238ee74ae9d025b878905";
-const ALIGO_TEMPLATE_CODE: &str = "TQ_0877";
-const ALIGO_SENDER_PHONE: &str = "01088074946";
-const ALIGO_MESSAGE_SUBJECT: &str = "이프 휴대폰 인증";
-const ALIGO_MESSAGE_PREFIX: &str = "이프 회원가입을 위해 인증번호 [";
-const ALIGO_MESSAGE_SUFFIX: &str = "]를 입력해주세요.";
-const ALIGO_TEST_MODE: bool = false;
+/// Minutes a verification code stays valid
+const CODE_LIFETIME_MIN: i64 = 30;
+/// Wrong guesses allowed before the verification code is invalidated
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// Seconds to wait before another code can be sent to the same phone
+const RESEND_COOLDOWN_SEC: i64 = 60;
+/// Codes that can be sent to a phone within a day
+const DAILY_PHONE_SEND_LIMIT: usize = 5;
+/// Codes that can be requested from an IP address within a day
+const DAILY_IP_SEND_LIMIT: usize = 20;
+/// Seconds to wait for a concurrent request for the same phone or IP address
+const SEND_LOCK_TIMEOUT_SEC: i64 = 10;
 
 #[derive(Debug, sqlx::FromRow)]
 pub(crate) struct PhoneAuthentication {
-    #[allow(dead_code)]
     id: u64,
-    #[allow(dead_code)]
-    phone: String,
+    phone: PhoneNumber,
     code: String,
+    failed_attempts: u32,
     created_at: DateTime<Utc>,
 }
 
-#[derive(Deserialize)]
-struct AligoTokenCreationResult {
-    code: i32,
-    #[allow(dead_code)]
-    message: String,
-    token: String,
-    /// URL encoded token
-    #[allow(dead_code)]
-    urlencode: String,
-}
-
-#[derive(Deserialize)]
-struct AligoSendResult {
-    code: i32,
-    #[allow(dead_code)]
-    message: String,
-    #[allow(dead_code)]
-    info: Option<AligoSendInfo>,
-}
-
-#[derive(Deserialize)]
-struct AligoSendInfo {
-    #[serde(rename = "type")]
-    #[allow(dead_code)]
-    send_type: String,
-    #[allow(dead_code)]
-    mid: Option<i64>,
-    #[allow(dead_code)]
-    current: String,
-    #[allow(dead_code)]
-    unit: f64,
-    #[allow(dead_code)]
-    total: f64,
-    #[allow(dead_code)]
-    scnt: Option<i64>,
-    #[allow(dead_code)]
-    fcnt: Option<i64>,
-}
-
 impl PhoneAuthentication {
-    // TODO: Send a verification code message to user
     pub(crate) async fn send(
-        phone: &str,
-        message_send: bool,
+        phone: &PhoneNumber,
+        ip_address: Option<IpAddr>,
+        message_provider: &dyn MessageProvider,
         db: &sqlx::Pool<MySql>,
     ) -> Result<Self> {
-        let tx = db.begin().await?;
+        let ip_address = ip_address.map(|ip_address| ip_address.to_string());
+        let mut conn = db.acquire().await?;
 
-        Self::cancel(phone, db).await?;
+        // The limits are checked and the send is logged under named locks on
+        // the phone and the IP address, so that concurrent requests cannot all
+        // pass the checks before any of them is logged. The phone is always
+        // locked first, so two requests cannot deadlock on each other.
+        let locks: Vec<String> = [
+            Some(format!("phone_authorization:{phone}")),
+            ip_address.as_ref().map(|ip_address| format!("phone_authorization_ip:{ip_address}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
 
-        let code = Self::generate_random_code();
+        let mut locked = vec![];
+        let result: Result<Self> = async {
+            for lock in &locks {
+                Self::lock(lock, &mut conn).await?;
+                locked.push(lock);
+            }
 
-        if message_send {
-            let body = [("apikey", ALIGO_API_KEY), ("userid", ALIGO_USER_ID)];
-            let token: String = reqwest::Client::new()
-                .post(
-                    ALIGO_HOST
-                        .join(ALIGO_TOKEN_CREATE_PATH)?
-                        .join(&format!("{}/", ALIGO_TOKEN_LIFETIME_SEC))?
-                        .join("s/")?,
-                )
-                .form(&body)
-                .send()
-                .await?
-                .json::<AligoTokenCreationResult>()
-                .await
-                .map_err(Into::into)
-                .and_then(|result| match result.code {
-                    0 => Ok(result),
-                    _ => Err(Error::MessageSend { code: result.code, message: result.message }),
-                })
-                .map(|result| result.token)?;
-
-            let body = [
-                ("apikey", ALIGO_API_KEY),
-                ("userid", ALIGO_USER_ID),
-                ("token", &token),
-                ("senderkey", ALIGO_SENDER_KEY),
-                ("tpl_code", ALIGO_TEMPLATE_CODE),
-                ("sender", ALIGO_SENDER_PHONE),
-                ("receiver_1", phone),
-                ("subject_1", ALIGO_MESSAGE_SUBJECT),
-                ("message_1", &format!("{ALIGO_MESSAGE_PREFIX}{code}{ALIGO_MESSAGE_SUFFIX}")),
-                ("failover", "N"),
-                ("testMode", if ALIGO_TEST_MODE { "Y" } else { "N" }),
-            ];
-            reqwest::Client::new()
-                .post(ALIGO_HOST.join(ALIGO_SEND_PATH)?)
-                .form(&body)
-                .send()
-                .await?
-                .json::<AligoSendResult>()
-                .await
-                .map_err(Error::from)
-                .and_then(|result| match result.code {
-                    0 => Ok(result),
-                    _ => Err(Error::MessageSend { code: result.code, message: result.message }),
-                })?;
+            Self::send_locked(phone, ip_address.as_deref(), message_provider, &mut conn).await
         }
+        .await;
+
+        // Locks are held until released even if the connection goes back to
+        // the pool, so they are released on errors too
+        for lock in locked {
+            sqlx::query("SELECT RELEASE_LOCK(?)").bind(lock).execute(&mut *conn).await?;
+        }
+
+        result
+    }
+
+    async fn send_locked(
+        phone: &PhoneNumber,
+        ip_address: Option<&str>,
+        message_provider: &dyn MessageProvider,
+        conn: &mut MySqlConnection,
+    ) -> Result<Self> {
+        Self::check_send_limit(phone, ip_address, conn).await?;
+
+        let mut tx = conn.begin().await?;
+
+        Self::cancel(phone, &mut *tx).await?;
+
+        let code = Self::generate_random_code();
+
+        message_provider.send_verification_code(phone, &code).await?;
+
+        sqlx::query!("DELETE FROM phone_authorization_log WHERE created_at < ?", Self::day_ago())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO phone_authorization_log (phone, ip_address) VALUES (?, ?)",
+            phone,
+            ip_address
+        )
+        .execute(&mut *tx)
+        .await?;
 
         sqlx::query!("INSERT INTO phone_authorization (phone, code) VALUES (?, ?)", phone, code)
-            .execute(db)
+            .execute(&mut *tx)
             .await?;
 
-        let result = Self::from_phone(phone, db).await;
+        let result = Self::from_phone(phone, &mut *tx).await?;
 
         tx.commit().await?;
 
-        result
+        Ok(result)
+    }
+
+    /// Waits for the named lock, which is held by the connection until it is
+    /// released.
+    async fn lock(name: &str, conn: &mut MySqlConnection) -> Result<()> {
+        let acquired: Option<i64> = sqlx::query_scalar("SELECT GET_LOCK(?, ?)")
+            .bind(name)
+            .bind(SEND_LOCK_TIMEOUT_SEC)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        match acquired {
+            Some(1) => Ok(()),
+            // Another code is being sent, which the cooldown will apply to
+            _ => Err(Error::ResendTooSoon { retry_after: RESEND_COOLDOWN_SEC }),
+        }
+    }
+
+    /// Issues the fixed code of a test account without sending any message.
+    pub(crate) async fn send_test(
+        test_account: &TestAccount,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Self> {
+        let mut tx = db.begin().await?;
+
+        Self::cancel(test_account.phone(), &mut *tx).await?;
+
+        sqlx::query!(
+            "INSERT INTO phone_authorization (phone, code) VALUES (?, ?)",
+            test_account.phone(),
+            test_account.code()
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let result = Self::from_phone(test_account.phone(), &mut *tx).await?;
+
+        tx.commit().await?;
+
+        Ok(result)
     }
 
-    pub(crate) async fn authorize(phone: &str, code: &str, db: &sqlx::Pool<MySql>) -> Result<()> {
+    pub(crate) async fn authorize(
+        phone: &PhoneNumber,
+        code: &str,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
         let data = Self::from_phone(phone, db).await?;
 
-        match (Utc::now() - data.created_at).num_minutes() {
-            minutes if minutes < 30 => match data.code == code {
-                true => Ok(()),
-                false => Err(Error::Verification),
-            },
-            _ => Err(Error::VerificationExpired),
+        if (Utc::now() - data.created_at).num_minutes() >= CODE_LIFETIME_MIN {
+            return Err(Error::VerificationExpired);
+        }
+
+        // Claim an attempt before comparing, so that concurrent guesses
+        // cannot all be compared against the same count
+        let claimed = sqlx::query!(
+            "UPDATE phone_authorization SET failed_attempts = failed_attempts + 1
+WHERE id = ? AND failed_attempts < ?",
+            data.id,
+            MAX_FAILED_ATTEMPTS
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        if claimed == 0 {
+            Self::cancel(phone, db).await?;
+
+            return Err(Error::TooManyAttempts);
         }
+
+        if data.code != code {
+            return Err(data.fail(db).await?);
+        }
+
+        sqlx::query!("UPDATE phone_authorization SET failed_attempts = 0 WHERE id = ?", data.id)
+            .execute(db)
+            .await?;
+
+        Ok(())
     }
 
-    pub(crate) async fn cancel(phone: &str, db: &sqlx::Pool<MySql>) -> Result<()> {
+    pub(crate) async fn cancel<'e>(
+        phone: &PhoneNumber,
+        executor: impl MySqlExecutor<'e>,
+    ) -> Result<()> {
         Ok(sqlx::query!("DELETE FROM phone_authorization WHERE phone = ?", phone)
-            .execute(db)
+            .execute(executor)
             .await
             .map(|_| ())?)
     }
@@ -171,17 +206,132 @@ impl PhoneAuthentication {
         &self.code
     }
 
-    async fn from_phone(phone: &str, db: &sqlx::Pool<MySql>) -> Result<Self> {
-        sqlx::query_as!(Self, "SELECT * FROM phone_authorization WHERE phone = ?", phone)
-            .fetch_one(db)
-            .await
-            .map_err(|error| match error {
-                sqlx::Error::RowNotFound => Error::Verification,
-                _ => Error::Database(error),
-            })
+    async fn from_phone<'e>(phone: &PhoneNumber, executor: impl MySqlExecutor<'e>) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            "SELECT
+id,
+phone as `phone: PhoneNumber`,
+code,
+failed_attempts,
+created_at
+FROM phone_authorization WHERE phone = ?",
+            phone
+        )
+        .fetch_one(executor)
+        .await
+        .map_err(|error| match error {
+            sqlx::Error::RowNotFound => Error::Verification,
+            _ => Error::Database(error),
+        })
+    }
+
+    /// Returns the error to respond with for a wrong guess, whose attempt has
+    /// already been claimed.
+    ///
+    /// The code is invalidated once the guesses reach the limit.
+    async fn fail(&self, db: &sqlx::Pool<MySql>) -> Result<Error> {
+        let invalidated = sqlx::query!(
+            "DELETE FROM phone_authorization WHERE id = ? AND failed_attempts >= ?",
+            self.id,
+            MAX_FAILED_ATTEMPTS
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        match invalidated {
+            0 => Ok(Error::Verification),
+            _ => Ok(Error::TooManyAttempts),
+        }
+    }
+
+    async fn check_send_limit(
+        phone: &PhoneNumber,
+        ip_address: Option<&str>,
+        conn: &mut MySqlConnection,
+    ) -> Result<()> {
+        let sent_at: Vec<DateTime<Utc>> = sqlx::query!(
+            "SELECT created_at FROM phone_authorization_log
+WHERE phone = ? AND created_at >= ? ORDER BY created_at ASC",
+            phone,
+            Self::day_ago()
+        )
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| row.created_at)
+        .collect();
+
+        if let Some(last_sent_at) = sent_at.last() {
+            let retry_after =
+                (*last_sent_at + Duration::seconds(RESEND_COOLDOWN_SEC) - Utc::now()).num_seconds();
+
+            if retry_after > 0 {
+                return Err(Error::ResendTooSoon { retry_after });
+            }
+        }
+
+        Self::check_daily_limit(&sent_at, DAILY_PHONE_SEND_LIMIT)?;
+
+        if let Some(ip_address) = ip_address {
+            let sent_at: Vec<DateTime<Utc>> = sqlx::query!(
+                "SELECT created_at FROM phone_authorization_log
+WHERE ip_address = ? AND created_at >= ? ORDER BY created_at ASC",
+                ip_address,
+                Self::day_ago()
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.created_at)
+            .collect();
+
+            Self::check_daily_limit(&sent_at, DAILY_IP_SEND_LIMIT)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_daily_limit(sent_at: &[DateTime<Utc>], limit: usize) -> Result<()> {
+        match sent_at.first() {
+            Some(first_sent_at) if sent_at.len() >= limit => Err(Error::SendLimitExceeded {
+                retry_after: (*first_sent_at + Duration::days(1) - Utc::now()).num_seconds(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn day_ago() -> DateTime<Utc> {
+        Utc::now() - Duration::days(1)
     }
 
     fn generate_random_code() -> String {
         format!("{:06}", rand::thread_rng().gen_range(100000..999999))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_are_allowed_under_the_daily_limit() {
+        let sent_at = [Utc::now() - Duration::hours(2), Utc::now() - Duration::hours(1)];
+
+        assert!(PhoneAuthentication::check_daily_limit(&[], 3).is_ok());
+        assert!(PhoneAuthentication::check_daily_limit(&sent_at, 3).is_ok());
+    }
+
+    #[test]
+    fn retry_is_allowed_a_day_after_the_first_send() {
+        let sent_at = [Utc::now() - Duration::hours(23), Utc::now() - Duration::hours(1)];
+
+        match PhoneAuthentication::check_daily_limit(&sent_at, 2) {
+            Err(Error::SendLimitExceeded { retry_after }) => {
+                assert!((3590..=3600).contains(&retry_after))
+            }
+            result => panic!("expected the send limit to be exceeded, got {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use sqlx::MySql;
+
+use crate::{
+    post::{comment::CommentId, PostId},
+    Result,
+};
+
+use super::account::{User, UserId};
+
+pub(crate) type BlockId = u64;
+
+/// A user blocked by the requester.
+pub(crate) struct UserBlock {
+    id: BlockId,
+    target_id: UserId,
+}
+
+/// A post blocked by the requester.
+pub(crate) struct PostBlock {
+    id: BlockId,
+    post_id: PostId,
+}
+
+/// A comment blocked by the requester.
+pub(crate) struct CommentBlock {
+    id: BlockId,
+    comment_id: CommentId,
+}
+
+impl UserBlock {
+    /// Blocks made by `user`, newest first. Deleted users are left out.
+    pub(crate) async fn from_user(
+        user: &User,
+        last_id: BlockId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT ub.id as `id: BlockId`, ub.target_id as `target_id: UserId`
+FROM user_block as ub INNER JOIN user as u ON u.id = ub.target_id WHERE
+ub.user_id = ? AND ub.id < ? AND u.deleted = FALSE
+ORDER BY ub.id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) fn id(&self) -> BlockId {
+        self.id
+    }
+
+    pub(crate) fn target_id(&self) -> UserId {
+        self.target_id
+    }
+}
+
+impl PostBlock {
+    /// Blocks made by `user`, newest first. Posts of deleted users are left out.
+    pub(crate) async fn from_user(
+        user: &User,
+        last_id: BlockId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT pb.id as `id: BlockId`, pb.post_id as `post_id: PostId`
+FROM post_block as pb INNER JOIN post as p ON p.id = pb.post_id WHERE
+pb.user_id = ? AND pb.id < ? AND
+p.author_id NOT IN (SELECT id FROM user WHERE deleted = TRUE)
+ORDER BY pb.id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) fn id(&self) -> BlockId {
+        self.id
+    }
+
+    pub(crate) fn post_id(&self) -> PostId {
+        self.post_id
+    }
+}
+
+impl CommentBlock {
+    /// Blocks made by `user`, newest first.
+    pub(crate) async fn from_user(
+        user: &User,
+        last_id: BlockId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT id as `id: BlockId`, comment_id as `comment_id: CommentId`
+FROM post_comment_block WHERE user_id = ? AND id < ?
+ORDER BY id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) fn id(&self) -> BlockId {
+        self.id
+    }
+
+    pub(crate) fn comment_id(&self) -> CommentId {
+        self.comment_id
+    }
+}
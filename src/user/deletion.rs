@@ -0,0 +1,222 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::{sync::Arc, time::Duration};
+
+use sqlx::{MySql, MySqlExecutor};
+use tracing::{error, info};
+
+use crate::{
+    aws::S3Client,
+    post::{Post, PostId},
+    AppState, Result,
+};
+
+use super::{
+    account::{UserId, DEFAULT_PICTURE_KEY},
+    export::DataExport,
+    verification::Verification,
+};
+
+/// How often the purge job looks for accounts past the grace period
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Steps of the account deletion lifecycle, logged one by one.
+#[derive(Debug, sqlx::Type, Clone, Copy)]
+#[repr(u32)]
+pub(crate) enum DeletionStep {
+    Requested = 1,
+    Restored = 2,
+    ProfilePictureDeleted = 3,
+    VerificationPictureDeleted = 4,
+    PostsDeleted = 5,
+    CommentsAnonymized = 6,
+    LikesDeleted = 7,
+    BlocksDeleted = 8,
+    Purged = 9,
+    DataExportDeleted = 10,
+}
+
+/// Deletes accounts for good once their grace period has passed.
+///
+/// A deleted account keeps every data until it is purged so that the owner
+/// can restore it by logging in again. Each step of the purge is written to
+/// `account_deletion_log`, which is kept after the account row is gone.
+pub(crate) struct AccountDeletion;
+
+struct PurgeTarget {
+    id: UserId,
+    picture: String,
+    picture_medium: Option<String>,
+    picture_small: Option<String>,
+}
+
+impl AccountDeletion {
+    pub(crate) async fn log<'e>(
+        executor: impl MySqlExecutor<'e>,
+        user_id: UserId,
+        step: DeletionStep,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO account_deletion_log (user_id, step, detail) VALUES (?, ?, ?)",
+            user_id,
+            step,
+            detail
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Runs the purge periodically in the background.
+    pub(crate) fn spawn_purge_job(state: Arc<AppState>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(err) = Self::purge_expired(
+                    state.config.account_deletion_grace_period_days(),
+                    &state.database,
+                    &state.s3,
+                )
+                .await
+                {
+                    error!("failed to purge deleted accounts: {err:?}");
+                }
+            }
+        });
+    }
+
+    /// Purges every account deleted more than `grace_period_days` days ago.
+    pub(crate) async fn purge_expired(
+        grace_period_days: i64,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<()> {
+        let targets = sqlx::query_as!(
+            PurgeTarget,
+            "SELECT id, picture, picture_medium, picture_small FROM user
+WHERE deleted = TRUE AND deleted_at < NOW() - INTERVAL ? DAY",
+            grace_period_days
+        )
+        .fetch_all(db)
+        .await?;
+
+        for target in targets {
+            // A failed account is retried on the next run, so keep going with the others
+            if let Err(err) = Self::purge(&target, db, s3).await {
+                error!("failed to purge account {}: {err:?}", target.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the data of the account in an order that can be resumed: the
+    /// account row, which the remaining steps look up, is deleted last.
+    async fn purge(target: &PurgeTarget, db: &sqlx::Pool<MySql>, s3: &S3Client) -> Result<()> {
+        let user_id = target.id;
+
+        let pictures = [
+            Some(target.picture.as_str()),
+            target.picture_medium.as_deref(),
+            target.picture_small.as_deref(),
+        ];
+
+        for key in pictures
+            .into_iter()
+            .flatten()
+            .filter_map(|url| s3.key_from_url(url))
+            .filter(|key| *key != DEFAULT_PICTURE_KEY)
+        {
+            s3.delete_file(key).await?;
+            Self::log(db, user_id, DeletionStep::ProfilePictureDeleted, Some(key)).await?;
+        }
+
+        for key in Verification::delete_pictures(user_id, db, s3).await? {
+            Self::log(db, user_id, DeletionStep::VerificationPictureDeleted, Some(&key)).await?;
+        }
+
+        for key in DataExport::delete_archives(user_id, db, s3).await? {
+            Self::log(db, user_id, DeletionStep::DataExportDeleted, Some(&key)).await?;
+        }
+
+        let post_ids: Vec<PostId> =
+            sqlx::query_scalar!("SELECT id as `id: PostId` FROM post WHERE author_id = ?", user_id)
+                .fetch_all(db)
+                .await?;
+        let total_posts = post_ids.len();
+
+        for post_id in post_ids {
+            Post::from_id_unscoped(post_id, db).await?.remove(db, s3).await?;
+        }
+        Self::log(db, user_id, DeletionStep::PostsDeleted, Some(&total_posts.to_string())).await?;
+
+        let mut tx = db.begin().await?;
+
+        let total_comments = sqlx::query!(
+            "UPDATE post_comment SET author_id = NULL, content = '', deleted = TRUE
+WHERE author_id = ?",
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        Self::log(
+            &mut *tx,
+            user_id,
+            DeletionStep::CommentsAnonymized,
+            Some(&total_comments.to_string()),
+        )
+        .await?;
+
+        let mut total_likes = sqlx::query!(
+            "DELETE FROM user_like WHERE issuer_id = ? OR target_id = ?",
+            user_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        total_likes += sqlx::query!("DELETE FROM post_like WHERE user_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        Self::log(&mut *tx, user_id, DeletionStep::LikesDeleted, Some(&total_likes.to_string()))
+            .await?;
+
+        let mut total_blocks = sqlx::query!(
+            "DELETE FROM user_block WHERE user_id = ? OR target_id = ?",
+            user_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        total_blocks += sqlx::query!("DELETE FROM post_block WHERE user_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        total_blocks += sqlx::query!("DELETE FROM post_comment_block WHERE user_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        Self::log(&mut *tx, user_id, DeletionStep::BlocksDeleted, Some(&total_blocks.to_string()))
+            .await?;
+
+        // Deleting the row frees the phone number and cascades to the sessions
+        sqlx::query!("DELETE FROM user WHERE id = ? AND deleted = TRUE", user_id)
+            .execute(&mut *tx)
+            .await?;
+        Self::log(&mut *tx, user_id, DeletionStep::Purged, None).await?;
+
+        tx.commit().await?;
+
+        info!("purged deleted account {user_id}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::{sync::Arc, time::Duration};
+
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::MySql;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+
+use crate::{
+    aws::S3Client,
+    post::{comment::Comment, gathering::Participant, Post, PostId},
+    schema::{
+        BlockedCommentItem, BlockedPostItem, BlockedUserItem, CommentGetResult,
+        JoinedGatheringItem, PostGetResult, PostLikeListItem, ReceivedPostLikeItem,
+        UserLikeListItem,
+    },
+    AppState, Error, Result,
+};
+
+use super::{
+    account::{User, UserId},
+    block::{CommentBlock, PostBlock, UserBlock},
+    like::{PostLike, UserLike},
+    privacy::PrivacySettings,
+    verification::Verification,
+};
+
+pub(crate) type ExportId = u64;
+
+const EXPORT_PATH: &str = "data_export/";
+
+/// Days an archive can be downloaded before it is deleted
+const ARCHIVE_RETENTION_DAYS: i64 = 7;
+
+/// How long a download link stays valid
+pub(crate) const DOWNLOAD_LINK_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// How often the cleanup job looks for expired archives
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Hours after which an export still being built is given up, as the server
+/// building it has been restarted
+const STALE_EXPORT_HOURS: i64 = 6;
+
+/// The loaders are paginated, but an export needs every row
+const ALL: i32 = i32::MAX;
+
+#[derive(Debug, sqlx::Type, Clone, Copy, PartialEq, Eq, Serialize)]
+#[repr(u32)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExportStatus {
+    Pending = 1,
+    Ready = 2,
+    Failed = 3,
+    Expired = 4,
+}
+
+/// A ZIP archive of every data kept about a user, built in the background
+/// for data access requests.
+#[derive(Debug, Serialize)]
+pub(crate) struct DataExport {
+    id: ExportId,
+    status: ExportStatus,
+    #[serde(skip)]
+    archive_key: Option<String>,
+    created_at: DateTime<Utc>,
+    completed_at: Option<DateTime<Utc>>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl DataExport {
+    /// Starts building an archive, or returns the one being built.
+    pub(crate) async fn request(user: &User, state: Arc<AppState>) -> Result<Self> {
+        let mut tx = state.database.begin().await?;
+
+        // Locking the user makes concurrent requests wait, so that only one
+        // of them starts building an archive
+        sqlx::query!("SELECT id FROM user WHERE id = ? FOR UPDATE", user.id())
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let pending = sqlx::query_scalar!(
+            "SELECT id as `id: ExportId` FROM data_export WHERE user_id = ? AND status = ?",
+            user.id(),
+            ExportStatus::Pending
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(id) = pending {
+            tx.commit().await?;
+
+            return Self::from_id(id, user, &state.database).await;
+        }
+
+        let id = sqlx::query!("INSERT INTO data_export (user_id) VALUES (?)", user.id())
+            .execute(&mut *tx)
+            .await?
+            .last_insert_id();
+
+        tx.commit().await?;
+
+        Self::spawn_build(id, user.clone(), state.clone());
+
+        Self::from_id(id, user, &state.database).await
+    }
+
+    pub(crate) async fn from_id(id: ExportId, user: &User, db: &sqlx::Pool<MySql>) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            "SELECT
+id as `id: ExportId`,
+status as `status: ExportStatus`,
+archive_key,
+created_at,
+completed_at,
+expires_at
+FROM data_export WHERE id = ? AND user_id = ?",
+            id,
+            user.id()
+        )
+        .fetch_optional(db)
+        .await?
+        .ok_or(Error::ExportNotFound(id))
+    }
+
+    /// Creates a short-lived link to the archive once it is ready.
+    pub(crate) async fn download_link(&self, s3: &S3Client) -> Result<Option<String>> {
+        match (self.status, &self.archive_key) {
+            (ExportStatus::Ready, Some(key)) => {
+                Ok(Some(s3.presigned_url(key, DOWNLOAD_LINK_LIFETIME).await?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Deletes the archives of the user that can still be downloaded.
+    pub(crate) async fn delete_archives(
+        user_id: UserId,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<Vec<String>> {
+        let rows = sqlx::query!(
+            "SELECT id as `id: ExportId`, archive_key as `archive_key!` FROM data_export
+WHERE user_id = ? AND archive_key IS NOT NULL",
+            user_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        let mut keys = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            Self::expire(row.id, &row.archive_key, db, s3).await?;
+            keys.push(row.archive_key);
+        }
+
+        Ok(keys)
+    }
+
+    /// Deletes expired archives and gives up stale exports periodically in
+    /// the background.
+    pub(crate) fn spawn_cleanup_job(state: Arc<AppState>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(err) = Self::clean_up(&state.database, &state.s3).await {
+                    error!("failed to clean up data exports: {err:?}");
+                }
+            }
+        });
+    }
+
+    async fn clean_up(db: &sqlx::Pool<MySql>, s3: &S3Client) -> Result<()> {
+        sqlx::query!(
+            "UPDATE data_export SET status = ?, completed_at = NOW()
+WHERE status = ? AND created_at < NOW() - INTERVAL ? HOUR",
+            ExportStatus::Failed,
+            ExportStatus::Pending,
+            STALE_EXPORT_HOURS
+        )
+        .execute(db)
+        .await?;
+
+        let expired = sqlx::query!(
+            "SELECT id as `id: ExportId`, archive_key as `archive_key!` FROM data_export
+WHERE archive_key IS NOT NULL AND expires_at < NOW()",
+        )
+        .fetch_all(db)
+        .await?;
+
+        for row in expired {
+            // A failed archive is retried on the next run, so keep going with the others
+            if let Err(err) = Self::expire(row.id, &row.archive_key, db, s3).await {
+                error!("failed to delete the archive of data export {}: {err:?}", row.id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The key is only cleared once the file is gone, so that a failed
+    /// deletion is retried.
+    async fn expire(id: ExportId, key: &str, db: &sqlx::Pool<MySql>, s3: &S3Client) -> Result<()> {
+        s3.delete_file(key).await?;
+
+        sqlx::query!(
+            "UPDATE data_export SET status = ?, archive_key = NULL WHERE id = ?",
+            ExportStatus::Expired,
+            id
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    fn spawn_build(id: ExportId, user: User, state: Arc<AppState>) {
+        tokio::spawn(async move {
+            let user_id = user.id();
+
+            let status = match Self::build(id, &user, &state.database, &state.s3).await {
+                Ok(key) => sqlx::query!(
+                    "UPDATE data_export SET
+status = ?,
+archive_key = ?,
+completed_at = NOW(),
+expires_at = NOW() + INTERVAL ? DAY
+WHERE id = ?",
+                    ExportStatus::Ready,
+                    key,
+                    ARCHIVE_RETENTION_DAYS,
+                    id
+                )
+                .execute(&state.database)
+                .await
+                .map(|_| ExportStatus::Ready),
+                Err(err) => {
+                    error!("failed to build data export {id} of user {user_id}: {err:?}");
+
+                    sqlx::query!(
+                        "UPDATE data_export SET status = ?, completed_at = NOW() WHERE id = ?",
+                        ExportStatus::Failed,
+                        id
+                    )
+                    .execute(&state.database)
+                    .await
+                    .map(|_| ExportStatus::Failed)
+                }
+            };
+
+            match status {
+                Ok(ExportStatus::Ready) => {
+                    info!("data export {id} of user {user_id} is ready");
+
+                    if let Err(err) = state
+                        .config
+                        .message_provider()
+                        .send_notification(user.phone(), EXPORT_READY_MESSAGE)
+                        .await
+                    {
+                        warn!("failed to notify user {user_id} of data export {id}: {err:?}");
+                    }
+                }
+                Ok(_) => (),
+                Err(err) => error!("failed to update the status of data export {id}: {err:?}"),
+            }
+        });
+    }
+
+    /// Writes the archive and uploads it, returning its storage key.
+    async fn build(
+        id: ExportId,
+        user: &User,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<String> {
+        let temp = NamedTempFile::new()
+            .map_err(|err| Error::Io { path: std::env::temp_dir(), source: err })?;
+        let io_error = |err| Error::Io { path: temp.path().to_path_buf(), source: err };
+        let file = tokio::fs::File::create(temp.path()).await.map_err(io_error)?;
+        let mut archive = Archive(ZipFileWriter::with_tokio(file));
+
+        archive.write_json("profile.json", &user.to_schema(db).await?).await?;
+        archive
+            .write_json("privacy.json", &PrivacySettings::from_user_id(user.id(), db).await?)
+            .await?;
+
+        let posts = Post::from_user(user, PostId::MAX, ALL, db).await?;
+        for post in &posts {
+            for (index, image) in post.images(db).await?.iter().enumerate() {
+                // Images of other buckets cannot be fetched, and are listed in posts.json anyway
+                let Some(key) = s3.key_from_url(image.url()) else {
+                    continue;
+                };
+                let name = key.rsplit('/').next().unwrap_or(key);
+
+                archive
+                    .write(&format!("posts/{}/{index}_{name}", post.id()), &s3.get_file(key).await?)
+                    .await?;
+            }
+        }
+        archive
+            .write_json("posts.json", &PostGetResult::from_posts(posts, user, db).await?)
+            .await?;
+
+        let mut comments = vec![];
+        for comment in Comment::from_author(user, u64::MAX, ALL, db).await? {
+            comments.push(CommentGetResult::from_comment(comment, db).await?);
+        }
+        archive.write_json("comments.json", &comments).await?;
+
+        let gatherings: Vec<JoinedGatheringItem> = Participant::from_user(user, u64::MAX, ALL, db)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        archive.write_json("joined_gatherings.json", &gatherings).await?;
+
+        let likes = UserLike::given(user, u64::MAX, ALL, db).await?;
+        let likes = likes.iter().map(|like| (like.id(), like.user_id())).collect();
+        archive
+            .write_json("likes_given.json", &UserLikeListItem::from_likes(likes, db).await?)
+            .await?;
+        let likes = UserLike::received(user, u64::MAX, ALL, db).await?;
+        let likes = likes.iter().map(|like| (like.id(), like.user_id())).collect();
+        archive
+            .write_json("likes_received.json", &UserLikeListItem::from_likes(likes, db).await?)
+            .await?;
+        let likes = PostLike::received(user, u64::MAX, ALL, db).await?;
+        let users = UserLikeListItem::from_likes(
+            likes.iter().map(|like| (like.id(), like.user_id())).collect(),
+            db,
+        )
+        .await?;
+        let likes: Vec<ReceivedPostLikeItem> = likes
+            .iter()
+            .zip(users)
+            .map(|(like, item)| ReceivedPostLikeItem { post_id: like.post_id(), like: item })
+            .collect();
+        archive.write_json("post_likes_received.json", &likes).await?;
+        let likes = PostLike::from_user(user, u64::MAX, ALL, db).await?;
+        archive
+            .write_json("liked_posts.json", &PostLikeListItem::from_likes(likes, user, db).await?)
+            .await?;
+
+        let blocks = UserBlock::from_user(user, u64::MAX, ALL, db).await?;
+        archive
+            .write_json("blocked_users.json", &BlockedUserItem::from_blocks(blocks, db).await?)
+            .await?;
+        let blocks = PostBlock::from_user(user, u64::MAX, ALL, db).await?;
+        archive
+            .write_json("blocked_posts.json", &BlockedPostItem::from_blocks(blocks, db).await?)
+            .await?;
+        let blocks = CommentBlock::from_user(user, u64::MAX, ALL, db).await?;
+        archive
+            .write_json(
+                "blocked_comments.json",
+                &BlockedCommentItem::from_blocks(blocks, db).await?,
+            )
+            .await?;
+
+        archive.write_json("verifications.json", &Verification::history(user, db).await?).await?;
+
+        let mut file =
+            archive.0.close().await.map_err(|err| Error::Unhandled(err.into()))?.into_inner();
+        file.flush().await.map_err(io_error)?;
+
+        let key = format!("{EXPORT_PATH}{}/{id}.zip", user.id());
+        s3.push_file(temp.path(), &key).await?;
+
+        Ok(key)
+    }
+}
+
+const EXPORT_READY_MESSAGE: &str =
+    "이프 개인정보 내보내기가 완료되었습니다. 7일 안에 앱에서 내려받아 주세요.";
+
+struct Archive(ZipFileWriter<tokio::fs::File>);
+
+impl Archive {
+    async fn write(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        self.0
+            .write_entry_whole(ZipEntryBuilder::new(name.into(), Compression::Deflate), data)
+            .await
+            .map_err(|err| Error::Unhandled(err.into()))
+    }
+
+    async fn write_json<T: Serialize>(&mut self, name: &str, value: &T) -> Result<()> {
+        let data = serde_json::to_vec_pretty(value).map_err(|err| Error::Unhandled(err.into()))?;
+
+        self.write(name, &data).await
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use sqlx::MySqlExecutor;
+
+use crate::Result;
+
+use super::account::UserId;
+
+/// Changes to an account that are kept for auditing.
+#[derive(Debug, sqlx::Type, Clone, Copy)]
+#[repr(u32)]
+pub(crate) enum AccountEvent {
+    PhoneChange = 1,
+    TownChange = 2,
+}
+
+pub(crate) struct AccountHistory;
+
+impl AccountHistory {
+    pub(crate) async fn record<'e>(
+        executor: impl MySqlExecutor<'e>,
+        user_id: UserId,
+        event: AccountEvent,
+        old_value: Option<&str>,
+        new_value: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO account_history (user_id, event, old_value, new_value) VALUES (?, ?, ?, ?)",
+            user_id,
+            event,
+            old_value,
+            new_value
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+}
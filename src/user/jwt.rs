@@ -12,13 +12,32 @@ use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use chrono::{Duration, Utc};
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use jsonwebtoken::Algorithm;
+use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
+use sqlx::MySql;
+use tracing::{error, info};
 
-use crate::{AppState, Error, Result};
+use crate::{config::KeySet, AppState, Error, Result};
 
-use super::account::{User, UserId};
+use super::{
+    account::{User, UserId},
+    role::Role,
+    session::{Session, SessionId},
+};
+
+/// How often the revoked tokens which have expired are deleted
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Purpose of a token, so that a refresh token cannot be used in place of an
+/// access token and the other way around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TokenType {
+    Access,
+    Refresh,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Claims {
@@ -31,33 +50,72 @@ struct Claims {
     exp: i64,
     /// Subject of the JWT (the user)
     sub: String,
+    /// Unique identifier of the JWT; used to revoke the JWT before it expires
+    jti: String,
+    /// Session of the user the JWT has been issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sid: Option<SessionId>,
+    /// Role of the user at the time the JWT was issued
+    #[serde(default)]
+    role: Role,
+    /// Type of the JWT
+    typ: TokenType,
 }
 
+#[derive(Clone)]
 pub(crate) struct Token {
     encoded_token: String,
+    id: String,
     user_id: UserId,
+    session_id: Option<SessionId>,
+    role: Role,
+    token_type: TokenType,
+    issued_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
 }
 
 impl Token {
     pub(crate) fn new(
-        private_key: &EncodingKey,
+        key_set: &KeySet,
+        token_type: TokenType,
         expires_in: Duration,
-        user_id: UserId,
+        user: &User,
+        session_id: SessionId,
     ) -> Result<Self> {
+        let id: String =
+            rand::thread_rng().sample_iter(Alphanumeric).take(32).map(char::from).collect();
+        // JWT times are in seconds, so keep the same precision in the token
+        let issued_at = Utc.timestamp_opt(Utc::now().timestamp(), 0).unwrap();
+        let expires_at = issued_at + expires_in;
         let claims = Claims {
             iss: env!("CARGO_PKG_HOMEPAGE").to_string() + "/api",
-            iat: Utc::now().timestamp(),
-            exp: (Utc::now() + expires_in).timestamp(),
-            sub: user_id.to_string(),
+            iat: issued_at.timestamp(),
+            exp: expires_at.timestamp(),
+            sub: user.id().to_string(),
+            jti: id.clone(),
+            sid: Some(session_id),
+            role: user.role(),
+            typ: token_type,
         };
 
-        Ok(jsonwebtoken::encode(&jsonwebtoken::Header::new(Algorithm::RS256), &claims, private_key)
-            .map(|token| Token { encoded_token: token, user_id })?)
+        let mut header = jsonwebtoken::Header::new(Algorithm::RS256);
+        header.kid = Some(key_set.signing_key_id().to_string());
+
+        Ok(jsonwebtoken::encode(&header, &claims, key_set.signing_key()).map(|token| Token {
+            encoded_token: token,
+            id,
+            user_id: user.id(),
+            session_id: Some(session_id),
+            role: user.role(),
+            token_type,
+            issued_at,
+            expires_at,
+        })?)
     }
 
     pub(crate) fn from_encoded_token(
         encoded_token: Option<&str>,
-        public_key: &DecodingKey,
+        key_set: &KeySet,
     ) -> Result<Self> {
         let encoded_token =
             encoded_token.ok_or(Error::TokenNotExists).and_then(|encoded_token| {
@@ -68,6 +126,10 @@ impl Token {
                 Ok(encoded_token.to_string())
             })?;
 
+        // Select the verification key the token has been signed with
+        let header = jsonwebtoken::decode_header(&encoded_token)?;
+        let public_key = key_set.decoding_key(header.kid.as_deref()).ok_or(Error::InvalidToken)?;
+
         let claims = jsonwebtoken::decode::<Claims>(
             &encoded_token,
             public_key,
@@ -78,16 +140,108 @@ impl Token {
         let user_id =
             claims.sub.parse::<UserId>().map_err(|err| Error::Unhandled(Box::new(err)))?;
 
-        Ok(Token { encoded_token, user_id })
+        let issued_at = Utc.timestamp_opt(claims.iat, 0).single().ok_or(Error::InvalidToken)?;
+        let expires_at = Utc.timestamp_opt(claims.exp, 0).single().ok_or(Error::InvalidToken)?;
+
+        Ok(Token {
+            encoded_token,
+            id: claims.jti,
+            user_id,
+            session_id: claims.sid,
+            role: claims.role,
+            token_type: claims.typ,
+            issued_at,
+            expires_at,
+        })
+    }
+
+    /// Adds the token to the revocation list so it is rejected until it expires.
+    pub(crate) async fn revoke(&self, db: &sqlx::Pool<MySql>) -> Result<()> {
+        sqlx::query!(
+            "INSERT IGNORE INTO revoked_token (id, user_id, expires_at) VALUES (?, ?, ?)",
+            self.id,
+            self.user_id,
+            self.expires_at
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn is_revoked(&self, db: &sqlx::Pool<MySql>) -> Result<bool> {
+        Ok(sqlx::query!("SELECT id FROM revoked_token WHERE id = ?", self.id)
+            .fetch_optional(db)
+            .await?
+            .is_some())
+    }
+
+    /// Deletes revoked tokens which have expired anyway.
+    pub(crate) async fn prune_revoked(db: &sqlx::Pool<MySql>) -> Result<u64> {
+        Ok(sqlx::query!("DELETE FROM revoked_token WHERE expires_at < NOW()")
+            .execute(db)
+            .await?
+            .rows_affected())
+    }
+
+    /// Prunes the revocation list periodically in the background.
+    pub(crate) fn spawn_pruning_job(state: Arc<AppState>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                // Expired tokens are rejected anyway, so their revocation can go
+                match Self::prune_revoked(&state.database).await {
+                    Ok(total) if total > 0 => info!("pruned {total} expired revoked tokens"),
+                    Ok(_) => (),
+                    Err(err) => error!("failed to prune revoked tokens: {err:?}"),
+                }
+            }
+        });
+    }
+
+    /// Rejects the token unless it has been issued as `token_type`.
+    pub(crate) fn expect_type(&self, token_type: TokenType) -> Result<()> {
+        if self.token_type != token_type {
+            return Err(Error::InvalidToken);
+        }
+
+        Ok(())
     }
 
     pub(crate) fn encoded_token(&self) -> &str {
         &self.encoded_token
     }
 
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
     pub(crate) fn user_id(&self) -> UserId {
         self.user_id
     }
+
+    pub(crate) fn session_id(&self) -> Option<SessionId> {
+        self.session_id
+    }
+
+    pub(crate) fn role(&self) -> Role {
+        self.role
+    }
+
+    pub(crate) fn token_type(&self) -> TokenType {
+        self.token_type
+    }
+
+    pub(crate) fn issued_at(&self) -> DateTime<Utc> {
+        self.issued_at
+    }
+
+    pub(crate) fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
 }
 
 pub(crate) async fn authorize_user_middleware(
@@ -104,19 +258,90 @@ pub(crate) async fn authorize_user_middleware(
         .map(|header| header.token().to_string())
         .map_err(|_| Error::TokenNotExists)
         .ok();
-    let user_id = authorize_user(access_token.as_deref(), state.config.public_key())
-        .await
-        .map(|token| token.user_id)?;
+    let token = authorize_user(access_token.as_deref(), state.config.key_set()).await?;
+
+    // Refresh tokens are only accepted by the refresh endpoint
+    token.expect_type(TokenType::Access)?;
+
+    // Reject the access token if it has been revoked before its expiration
+    if token.is_revoked(&state.database).await? {
+        return Err(Error::RevokedToken);
+    }
+
+    // Logging out revokes the session, which ends every token issued for it
+    let session_id = token.session_id().ok_or(Error::InvalidToken)?;
+    if Session::from_id(session_id, &state.database).await?.is_revoked() {
+        return Err(Error::RevokedToken);
+    }
 
     let mut req = extract::Request::from_parts(parts, body);
 
     // Include the account data to extensions
-    req.extensions_mut().insert(User::from_id(user_id, &state.database).await?);
+    req.extensions_mut().insert(User::from_id(token.user_id(), &state.database).await?);
+    req.extensions_mut().insert(token);
 
     // Execute the next middleware
     Ok(next.run(req).await)
 }
 
-pub(crate) async fn authorize_user(token: Option<&str>, public_key: &DecodingKey) -> Result<Token> {
-    Token::from_encoded_token(token, public_key)
+pub(crate) async fn authorize_user(token: Option<&str>, key_set: &KeySet) -> Result<Token> {
+    Token::from_encoded_token(token, key_set)
+}
+
+#[cfg(test)]
+impl Token {
+    /// A token which is not signed, for checking what is read from its claims.
+    pub(crate) fn unsigned(
+        user_id: UserId,
+        session_id: SessionId,
+        token_type: TokenType,
+        issued_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            encoded_token: format!("{user_id}.{session_id}.{}", issued_at.timestamp()),
+            id: format!("{token_type:?}{}", issued_at.timestamp()),
+            user_id,
+            session_id: Some(session_id),
+            role: Role::User,
+            token_type,
+            issued_at,
+            expires_at: issued_at + Duration::hours(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn claims_need_an_id_and_a_type() {
+        let claims = json!({"iss": "downtown", "iat": 0, "exp": 60, "sub": "1"});
+
+        assert!(serde_json::from_value::<Claims>(claims.clone()).is_err());
+
+        let mut with_id = claims.clone();
+        with_id["jti"] = json!("a");
+        assert!(serde_json::from_value::<Claims>(with_id.clone()).is_err());
+
+        let mut with_type = with_id;
+        with_type["typ"] = json!("refresh");
+        let claims = serde_json::from_value::<Claims>(with_type).unwrap();
+        assert_eq!(claims.typ, TokenType::Refresh);
+        assert_eq!(claims.role, Role::User);
+        assert_eq!(claims.sid, None);
+    }
+
+    #[test]
+    fn tokens_are_only_accepted_as_their_type() {
+        let access = Token::unsigned(1, 1, TokenType::Access, Utc::now());
+        let refresh = Token::unsigned(1, 1, TokenType::Refresh, Utc::now());
+
+        assert!(access.expect_type(TokenType::Access).is_ok());
+        assert!(refresh.expect_type(TokenType::Refresh).is_ok());
+        assert!(matches!(access.expect_type(TokenType::Refresh), Err(Error::InvalidToken)));
+        assert!(matches!(refresh.expect_type(TokenType::Access), Err(Error::InvalidToken)));
+    }
 }
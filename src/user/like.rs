@@ -0,0 +1,176 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use sqlx::MySql;
+
+use crate::{post::PostId, Result};
+
+use super::account::{User, UserId};
+
+pub(crate) type LikeId = u64;
+
+/// A like between two users, seen from one side of it.
+pub(crate) struct UserLike {
+    id: LikeId,
+    user_id: UserId,
+}
+
+/// A like on a post, by the user or to the post being listed.
+pub(crate) struct PostLike {
+    id: LikeId,
+    user_id: UserId,
+    post_id: PostId,
+}
+
+impl UserLike {
+    /// Users who liked `user`, newest first.
+    pub(crate) async fn received(
+        user: &User,
+        last_id: LikeId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT ul.id as `id: LikeId`, ul.issuer_id as `user_id: UserId`
+FROM user_like as ul INNER JOIN user as u ON u.id = ul.issuer_id WHERE
+ul.target_id = ? AND ul.id < ? AND u.deleted = FALSE AND
+ul.issuer_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?)
+ORDER BY ul.id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            user.id(),
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    /// Users whom `user` liked, newest first.
+    pub(crate) async fn given(
+        user: &User,
+        last_id: LikeId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT ul.id as `id: LikeId`, ul.target_id as `user_id: UserId`
+FROM user_like as ul INNER JOIN user as u ON u.id = ul.target_id WHERE
+ul.issuer_id = ? AND ul.id < ? AND u.deleted = FALSE AND
+ul.target_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?)
+ORDER BY ul.id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            user.id(),
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) fn id(&self) -> LikeId {
+        self.id
+    }
+
+    pub(crate) fn user_id(&self) -> UserId {
+        self.user_id
+    }
+}
+
+impl PostLike {
+    /// Users who liked the post, as seen by `user`, newest first.
+    pub(crate) async fn from_post_id(
+        post_id: PostId,
+        user: &User,
+        last_id: LikeId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+pl.id as `id: LikeId`,
+pl.user_id as `user_id: UserId`,
+pl.post_id as `post_id: PostId`
+FROM post_like as pl INNER JOIN user as u ON u.id = pl.user_id WHERE
+pl.post_id = ? AND pl.id < ? AND u.deleted = FALSE AND
+pl.user_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?)
+ORDER BY pl.id DESC LIMIT ?",
+            post_id,
+            last_id,
+            user.id(),
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    /// Likes on the posts of `user`, newest first.
+    pub(crate) async fn received(
+        user: &User,
+        last_id: LikeId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+pl.id as `id: LikeId`,
+pl.user_id as `user_id: UserId`,
+pl.post_id as `post_id: PostId`
+FROM post_like as pl
+INNER JOIN post as p ON p.id = pl.post_id
+INNER JOIN user as u ON u.id = pl.user_id WHERE
+p.author_id = ? AND pl.id < ? AND u.deleted = FALSE AND
+pl.user_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?)
+ORDER BY pl.id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            user.id(),
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    /// Posts liked by `user`, newest like first.
+    pub(crate) async fn from_user(
+        user: &User,
+        last_id: LikeId,
+        limit: i32,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+pl.id as `id: LikeId`,
+pl.user_id as `user_id: UserId`,
+pl.post_id as `post_id: PostId`
+FROM post_like as pl INNER JOIN post as p ON p.id = pl.post_id WHERE
+pl.user_id = ? AND pl.id < ? AND
+p.author_id NOT IN (SELECT id FROM user WHERE deleted = TRUE) AND
+p.author_id NOT IN (SELECT target_id FROM user_block WHERE user_id = ?) AND
+p.id NOT IN (SELECT post_id FROM post_block WHERE user_id = ?)
+ORDER BY pl.id DESC LIMIT ?",
+            user.id(),
+            last_id,
+            user.id(),
+            user.id(),
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) fn id(&self) -> LikeId {
+        self.id
+    }
+
+    pub(crate) fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    pub(crate) fn post_id(&self) -> PostId {
+        self.post_id
+    }
+}
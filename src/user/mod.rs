@@ -2,7 +2,19 @@
 
 pub(crate) mod account;
 pub(crate) mod authentication;
+pub(crate) mod block;
+pub(crate) mod deletion;
+pub(crate) mod export;
+pub(crate) mod history;
 pub(crate) mod jwt;
+pub(crate) mod like;
+pub(crate) mod phone;
+pub(crate) mod privacy;
+pub(crate) mod profile;
+pub(crate) mod role;
+pub(crate) mod session;
+pub(crate) mod test_account;
+pub(crate) mod verification;
 
 use std::str::FromStr;
 
@@ -0,0 +1,170 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::str::FromStr;
+
+use axum::{async_trait, body};
+use axum_typed_multipart::{FieldMetadata, TryFromChunks, TypedMultipartError};
+use serde::Serialize;
+
+use crate::Error;
+
+/// Country calling code assumed for numbers written in the national format
+const DEFAULT_COUNTRY_CODE: &str = "82";
+
+/// A phone number normalized to the E.164 format (e.g. `+821012345678`).
+///
+/// Numbers starting with `0` are treated as Korean national numbers, and
+/// international numbers have to start with `+`. Any other number is rejected,
+/// as it could be read either way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, sqlx::Type)]
+#[serde(transparent)]
+#[sqlx(transparent)]
+pub struct PhoneNumber(String);
+
+impl PhoneNumber {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the number in the national format if it belongs to the default
+    /// country, which domestic message services expect.
+    pub fn to_national(&self) -> String {
+        match self.0.strip_prefix(&format!("+{DEFAULT_COUNTRY_CODE}")) {
+            Some(number) => format!("0{number}"),
+            None => self.0.clone(),
+        }
+    }
+}
+
+impl FromStr for PhoneNumber {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidPhoneNumber(s.to_string());
+        let trimmed = s.trim();
+        let (international, number) = match trimmed.strip_prefix('+') {
+            Some(number) => (true, number),
+            None => (false, trimmed),
+        };
+
+        if !number.chars().all(|c| c.is_ascii_digit() || matches!(c, '-' | ' ' | '.' | '(' | ')')) {
+            return Err(invalid());
+        }
+
+        let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+        let digits = match digits.strip_prefix('0') {
+            Some(national) if !international => {
+                // Korean national numbers have 8 to 10 digits without the trunk prefix
+                if !(8..=10).contains(&national.len()) {
+                    return Err(invalid());
+                }
+
+                format!("{DEFAULT_COUNTRY_CODE}{national}")
+            }
+            Some(_) => return Err(invalid()),
+            None if international => digits,
+            None => return Err(invalid()),
+        };
+
+        // E.164 allows up to 15 digits including the country code
+        if !(8..=15).contains(&digits.len()) {
+            return Err(invalid());
+        }
+
+        Ok(Self(format!("+{digits}")))
+    }
+}
+
+impl std::fmt::Display for PhoneNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[async_trait]
+impl TryFromChunks for PhoneNumber {
+    async fn try_from_chunks(
+        chunks: impl futures_util::stream::Stream<Item = Result<body::Bytes, TypedMultipartError>>
+            + Send
+            + Sync
+            + Unpin,
+        metadata: FieldMetadata,
+    ) -> Result<Self, TypedMultipartError> {
+        let field_name = metadata.name.clone().unwrap_or_default();
+        let value = String::try_from_chunks(chunks, metadata).await?;
+
+        value.parse().map_err(|err: Error| TypedMultipartError::WrongFieldType {
+            field_name,
+            wanted_type: String::from("PhoneNumber"),
+            source: err.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(number: &str) -> Option<String> {
+        number.parse::<PhoneNumber>().ok().map(|number| number.as_str().to_string())
+    }
+
+    #[test]
+    fn national_numbers_get_the_default_country_code() {
+        assert_eq!(parse("010-1234-5678").as_deref(), Some("+821012345678"));
+        assert_eq!(parse(" 01012345678 ").as_deref(), Some("+821012345678"));
+        assert_eq!(parse("02.123.4567").as_deref(), Some("+8221234567"));
+    }
+
+    #[test]
+    fn international_numbers_keep_their_country_code() {
+        assert_eq!(parse("+82 10 1234 5678").as_deref(), Some("+821012345678"));
+        assert_eq!(parse("+1 (415) 555-0100").as_deref(), Some("+14155550100"));
+    }
+
+    #[test]
+    fn international_numbers_need_a_plus_sign() {
+        assert_eq!(parse("821012345678"), None);
+        assert_eq!(parse("1012345678"), None);
+    }
+
+    #[test]
+    fn trunk_prefix_is_only_allowed_in_national_numbers() {
+        assert_eq!(parse("+01012345678"), None);
+        assert_eq!(parse("+0821012345678"), None);
+    }
+
+    #[test]
+    fn national_numbers_have_8_to_10_digits_after_the_trunk_prefix() {
+        assert_eq!(parse("0123456"), None);
+        assert_eq!(parse("012345678").as_deref(), Some("+8212345678"));
+        assert_eq!(parse("01234567890").as_deref(), Some("+821234567890"));
+        assert_eq!(parse("012345678901"), None);
+    }
+
+    #[test]
+    fn numbers_have_8_to_15_digits() {
+        assert_eq!(parse("+1234567"), None);
+        assert_eq!(parse("+12345678").as_deref(), Some("+12345678"));
+        assert_eq!(parse("+123456789012345").as_deref(), Some("+123456789012345"));
+        assert_eq!(parse("+1234567890123456"), None);
+    }
+
+    #[test]
+    fn rejects_other_characters() {
+        assert_eq!(parse("010-1234-567a"), None);
+        assert_eq!(parse("010/1234/5678"), None);
+        assert_eq!(parse("++821012345678"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn national_format_is_only_used_for_the_default_country() {
+        assert_eq!(parse_number("+821012345678").to_national(), "01012345678");
+        assert_eq!(parse_number("+14155550100").to_national(), "+14155550100");
+    }
+
+    fn parse_number(number: &str) -> PhoneNumber {
+        number.parse().unwrap()
+    }
+}
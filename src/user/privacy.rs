@@ -0,0 +1,112 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::MySql;
+
+use crate::{schema::PrivacySettingsUpdateSchema, Result};
+
+use super::{account::UserId, profile::age};
+
+/// What other users can see about an account.
+///
+/// Hidden birthdates are replaced with the age band, so only whether someone
+/// is in their twenties or thirties is revealed.
+#[derive(Debug, Serialize, Clone, Copy)]
+pub(crate) struct PrivacySettings {
+    #[serde(skip)]
+    user_id: UserId,
+    show_birthdate: bool,
+    show_phone: bool,
+    show_total_likes: bool,
+}
+
+impl PrivacySettings {
+    pub(crate) async fn from_user_id(user_id: UserId, db: &sqlx::Pool<MySql>) -> Result<Self> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+id as `user_id: UserId`,
+show_birthdate as `show_birthdate: _`,
+show_phone as `show_phone: _`,
+show_total_likes as `show_total_likes: _`
+FROM user WHERE id = ?",
+            user_id
+        )
+        .fetch_one(db)
+        .await?)
+    }
+
+    /// Changes the given settings, leaving the others as they are.
+    pub(crate) async fn update(
+        &mut self,
+        data: PrivacySettingsUpdateSchema,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
+        let show_birthdate = data.show_birthdate.unwrap_or(self.show_birthdate);
+        let show_phone = data.show_phone.unwrap_or(self.show_phone);
+        let show_total_likes = data.show_total_likes.unwrap_or(self.show_total_likes);
+
+        sqlx::query!(
+            "UPDATE user SET show_birthdate = ?, show_phone = ?, show_total_likes = ? WHERE id = ?",
+            show_birthdate,
+            show_phone,
+            show_total_likes,
+            self.user_id
+        )
+        .execute(db)
+        .await?;
+
+        self.show_birthdate = show_birthdate;
+        self.show_phone = show_phone;
+        self.show_total_likes = show_total_likes;
+
+        Ok(())
+    }
+
+    pub(crate) fn show_birthdate(&self) -> bool {
+        self.show_birthdate
+    }
+
+    pub(crate) fn show_phone(&self) -> bool {
+        self.show_phone
+    }
+
+    pub(crate) fn show_total_likes(&self) -> bool {
+        self.show_total_likes
+    }
+}
+
+/// Returns the decade of the age, e.g. 20 for anyone from 20 to 29 years old.
+pub(crate) fn age_band(birthdate: NaiveDate) -> u32 {
+    (age(birthdate).max(0) as u32) / 10 * 10
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Days, Months, Utc};
+
+    use super::*;
+
+    fn years_ago(years: u32) -> NaiveDate {
+        Utc::now().date_naive().checked_sub_months(Months::new(years * 12)).unwrap()
+    }
+
+    #[test]
+    fn age_band_is_the_decade_of_the_age() {
+        assert_eq!(age_band(years_ago(20)), 20);
+        assert_eq!(age_band(years_ago(29)), 20);
+        assert_eq!(age_band(years_ago(35)), 30);
+    }
+
+    #[test]
+    fn age_band_changes_on_the_birthday() {
+        assert_eq!(age_band(years_ago(30)), 30);
+        assert_eq!(age_band(years_ago(30) + Days::new(1)), 20);
+    }
+
+    #[test]
+    fn age_band_of_a_future_birthdate_is_zero() {
+        assert_eq!(age_band(Utc::now().date_naive() + Days::new(1)), 0);
+    }
+}
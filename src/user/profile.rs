@@ -0,0 +1,141 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use chrono::{Datelike, NaiveDate, Utc};
+
+use crate::{Error, Result};
+
+use super::Sex;
+
+/// Length of `user.name` in characters
+const NAME_MAX_LENGTH: usize = 8;
+/// Length of `user.bio` in characters
+const BIO_MAX_LENGTH: usize = 512;
+/// Users under this age cannot sign up
+const MINIMUM_AGE: i32 = 14;
+
+/// Profile fields the user can change after the registration, each checked
+/// by the validation rules below. `None` keeps the current value.
+#[derive(Debug, Default)]
+pub(crate) struct ProfileUpdate {
+    pub(crate) name: Option<String>,
+    pub(crate) birthdate: Option<NaiveDate>,
+    pub(crate) sex: Option<Sex>,
+    pub(crate) bio: Option<String>,
+}
+
+/// Trims the name, which may contain Hangul, Latin letters and single spaces
+/// between words.
+pub(crate) fn validate_name(name: &str) -> Result<String> {
+    let invalid = |reason: &str| Error::InvalidField { field: "name", reason: reason.to_string() };
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(invalid("must not be empty"));
+    }
+    if name.chars().count() > NAME_MAX_LENGTH {
+        return Err(invalid(&format!("must be at most {NAME_MAX_LENGTH} characters")));
+    }
+    if !name.chars().all(|c| is_hangul(c) || c.is_ascii_alphabetic() || c == ' ') {
+        return Err(invalid("must only contain Hangul, Latin letters and spaces"));
+    }
+    if name.contains("  ") {
+        return Err(invalid("must not contain consecutive spaces"));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Parses a `YYYY-MM-DD` date of someone at least `MINIMUM_AGE` years old.
+pub(crate) fn validate_birthdate(birthdate: &str) -> Result<NaiveDate> {
+    let invalid =
+        |reason: &str| Error::InvalidField { field: "birthdate", reason: reason.to_string() };
+    let birthdate = NaiveDate::parse_from_str(birthdate.trim(), "%Y-%m-%d")
+        .map_err(|_| invalid("must be a date in the YYYY-MM-DD format"))?;
+
+    if birthdate > Utc::now().date_naive() {
+        return Err(invalid("must not be in the future"));
+    }
+    if age(birthdate) < MINIMUM_AGE {
+        return Err(invalid(&format!("must be at least {MINIMUM_AGE} years ago")));
+    }
+
+    Ok(birthdate)
+}
+
+pub(crate) fn validate_bio(bio: &str) -> Result<String> {
+    if bio.chars().count() > BIO_MAX_LENGTH {
+        return Err(Error::InvalidField {
+            field: "bio",
+            reason: format!("must be at most {BIO_MAX_LENGTH} characters"),
+        });
+    }
+
+    Ok(bio.to_string())
+}
+
+/// Age in full years as of today.
+pub(crate) fn age(birthdate: NaiveDate) -> i32 {
+    let today = Utc::now().date_naive();
+    let had_birthday = (today.month(), today.day()) >= (birthdate.month(), birthdate.day());
+
+    today.year() - birthdate.year() - if had_birthday { 0 } else { 1 }
+}
+
+/// Complete Hangul syllables, without standalone consonants and vowels
+fn is_hangul(c: char) -> bool {
+    ('가'..='힣').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Days, Months};
+
+    use super::*;
+
+    fn years_ago(years: u32) -> NaiveDate {
+        Utc::now().date_naive().checked_sub_months(Months::new(years * 12)).unwrap()
+    }
+
+    #[test]
+    fn name_is_trimmed() {
+        assert_eq!(validate_name("  홍길동 ").unwrap(), "홍길동");
+        assert_eq!(validate_name("Kim Min").unwrap(), "Kim Min");
+    }
+
+    #[test]
+    fn name_has_1_to_8_characters() {
+        assert!(validate_name("   ").is_err());
+        assert!(validate_name("가나다라마바사아").is_ok());
+        assert!(validate_name("가나다라마바사아자").is_err());
+    }
+
+    #[test]
+    fn name_only_contains_hangul_latin_letters_and_single_spaces() {
+        assert!(validate_name("홍길동1").is_err());
+        assert!(validate_name("ㅎㄱㄷ").is_err());
+        assert!(validate_name("Jean-Luc").is_err());
+        assert!(validate_name("Kim  Min").is_err());
+    }
+
+    #[test]
+    fn birthdate_is_a_past_date() {
+        assert_eq!(
+            validate_birthdate(" 2000-02-29 ").unwrap(),
+            NaiveDate::from_ymd_opt(2000, 2, 29).unwrap()
+        );
+        assert!(validate_birthdate("2000/02/29").is_err());
+        assert!(validate_birthdate("2001-02-29").is_err());
+
+        let tomorrow = Utc::now().date_naive() + Days::new(1);
+        assert!(validate_birthdate(&tomorrow.format("%Y-%m-%d").to_string()).is_err());
+    }
+
+    #[test]
+    fn birthdate_is_of_someone_old_enough() {
+        let oldest = years_ago(MINIMUM_AGE as u32);
+        let too_young = oldest + Days::new(1);
+
+        assert!(validate_birthdate(&oldest.format("%Y-%m-%d").to_string()).is_ok());
+        assert!(validate_birthdate(&too_young.format("%Y-%m-%d").to_string()).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::marker::PhantomData;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use axum_typed_multipart::TryFromField;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+use super::{account::User, jwt::Token};
+
+/// Permission level of a user. A higher role includes every lower one.
+#[derive(
+    Debug,
+    Default,
+    TryFromField,
+    sqlx::Type,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+#[repr(u32)]
+#[try_from_field(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User = 1,
+    Moderator = 2,
+    Admin = 3,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        };
+
+        write!(f, "{s}")
+    }
+}
+
+pub(crate) trait RoleRequirement: Send + Sync {
+    const ROLE: Role;
+}
+
+pub(crate) struct Moderator;
+
+impl RoleRequirement for Moderator {
+    const ROLE: Role = Role::Moderator;
+}
+
+pub(crate) struct Admin;
+
+impl RoleRequirement for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Rejects the request unless the user has at least the role `R`.
+///
+/// Must be used behind the authorization middleware. Both the role claim of
+/// the access token and the current role of the account are checked, so a
+/// demoted user loses the permission without waiting for the token to expire.
+pub(crate) struct RequireRole<R: RoleRequirement>(PhantomData<R>);
+
+impl<R: RoleRequirement> RequireRole<R> {
+    /// Permits the request if both the account and its token have the role.
+    fn check(user_role: Role, token_role: Role) -> Result<Self, Error> {
+        if user_role < R::ROLE || token_role < R::ROLE {
+            return Err(Error::PermissionDenied);
+        }
+
+        Ok(Self(PhantomData))
+    }
+}
+
+#[async_trait]
+impl<R, S> FromRequestParts<S> for RequireRole<R>
+where
+    R: RoleRequirement,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user = parts.extensions.get::<User>().ok_or(Error::TokenNotExists)?;
+        let token = parts.extensions.get::<Token>().ok_or(Error::TokenNotExists)?;
+
+        Self::check(user.role(), token.role())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permits<R: RoleRequirement>(user_role: Role, token_role: Role) -> bool {
+        RequireRole::<R>::check(user_role, token_role).is_ok()
+    }
+
+    #[test]
+    fn higher_roles_include_lower_ones() {
+        assert!(Role::User < Role::Moderator);
+        assert!(Role::Moderator < Role::Admin);
+        assert_eq!(Role::default(), Role::User);
+    }
+
+    #[test]
+    fn roles_are_named_in_snake_case() {
+        assert_eq!(Role::Moderator.to_string(), "moderator");
+        assert_eq!(serde_json::to_string(&Role::Admin).unwrap(), "\"admin\"");
+        assert_eq!(serde_json::from_str::<Role>("\"moderator\"").unwrap(), Role::Moderator);
+    }
+
+    #[test]
+    fn required_role_or_higher_is_permitted() {
+        assert!(!permits::<Moderator>(Role::User, Role::User));
+        assert!(permits::<Moderator>(Role::Moderator, Role::Moderator));
+        assert!(permits::<Moderator>(Role::Admin, Role::Admin));
+        assert!(!permits::<Admin>(Role::Moderator, Role::Moderator));
+    }
+
+    #[test]
+    fn account_and_token_both_need_the_role() {
+        // Demoted after the token was issued
+        assert!(!permits::<Admin>(Role::User, Role::Admin));
+        // Promoted after the token was issued
+        assert!(!permits::<Admin>(Role::Admin, Role::User));
+    }
+}
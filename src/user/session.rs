@@ -0,0 +1,263 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use chrono::{DateTime, Utc};
+use sqlx::MySql;
+use tracing::warn;
+
+use crate::{Error, Result};
+
+use super::{
+    account::{User, UserId},
+    jwt::{Token, TokenType},
+};
+
+pub(crate) type SessionId = u64;
+
+/// A login of a user on a single device.
+///
+/// Every refresh rotates the refresh token stored here. A refresh token issued
+/// for this session before the stored one has been replayed, so the whole
+/// session is revoked.
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct Session {
+    id: SessionId,
+    user_id: UserId,
+    refresh_token: Option<String>,
+    refresh_token_issued_at: Option<DateTime<Utc>>,
+    revoked: bool,
+    created_at: DateTime<Utc>,
+}
+
+impl Session {
+    pub(crate) async fn create(
+        user: &User,
+        user_agent: Option<&str>,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Self> {
+        let id = sqlx::query!(
+            "INSERT INTO user_session (user_id, user_agent) VALUES (?, ?)",
+            user.id(),
+            user_agent
+        )
+        .execute(db)
+        .await
+        .map(|row| row.last_insert_id())?;
+
+        Self::from_id(id, db).await
+    }
+
+    pub(crate) async fn from_id(id: SessionId, db: &sqlx::Pool<MySql>) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            "SELECT
+id,
+user_id,
+refresh_token,
+refresh_token_issued_at,
+revoked as `revoked: _`,
+created_at
+FROM user_session WHERE id = ?",
+            id
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => Error::InvalidToken,
+            _ => Error::Database(err),
+        })
+    }
+
+    /// Checks the refresh token against the latest one issued for this session.
+    ///
+    /// A token rotated out of this session means it has been replayed, so the
+    /// session is revoked before returning an error. Any other mismatch, such
+    /// as an access token sent by mistake, is only rejected.
+    pub(crate) async fn verify_refresh_token(
+        &mut self,
+        refresh_token: &Token,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
+        if self.revoked || self.user_id != refresh_token.user_id() {
+            return Err(Error::InvalidToken);
+        }
+
+        if self.refresh_token.as_deref() == Some(refresh_token.encoded_token()) {
+            return Ok(());
+        }
+
+        if self.issued_before_latest(refresh_token) {
+            self.revoke_on_reuse(db).await?;
+
+            return Err(Error::RefreshTokenReused);
+        }
+
+        Err(Error::InvalidToken)
+    }
+
+    /// Whether the token was issued for this session before its latest
+    /// refresh token, which means it has been rotated out.
+    fn issued_before_latest(&self, token: &Token) -> bool {
+        if token.session_id() != Some(self.id) || token.token_type() != TokenType::Refresh {
+            return false;
+        }
+
+        self.refresh_token_issued_at.is_some_and(|latest| {
+            token.issued_at() >= self.created_at && token.issued_at() <= latest
+        })
+    }
+
+    /// Replaces the tokens of the session with a newly issued pair.
+    ///
+    /// The refresh token is only replaced if it is still the one verified, so
+    /// that one of two concurrent refreshes fails. It is taken as a reuse.
+    pub(crate) async fn rotate(
+        &mut self,
+        access_token: &Token,
+        refresh_token: &Token,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
+        let rotated = sqlx::query!(
+            "UPDATE user_session SET
+refresh_token = ?,
+refresh_token_id = ?,
+refresh_token_issued_at = ?,
+access_token_id = ?,
+access_token_expires_at = ?
+WHERE id = ? AND revoked = FALSE AND refresh_token <=> ?",
+            refresh_token.encoded_token(),
+            refresh_token.id(),
+            refresh_token.issued_at(),
+            access_token.id(),
+            access_token.expires_at(),
+            self.id,
+            self.refresh_token
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        if rotated == 0 {
+            self.revoke_on_reuse(db).await?;
+
+            return Err(Error::RefreshTokenReused);
+        }
+
+        self.refresh_token = Some(refresh_token.encoded_token().to_string());
+        self.refresh_token_issued_at = Some(refresh_token.issued_at());
+
+        Ok(())
+    }
+
+    /// Revokes the session and the latest access token issued for it.
+    pub(crate) async fn revoke(&mut self, db: &sqlx::Pool<MySql>) -> Result<()> {
+        let mut tx = db.begin().await?;
+
+        sqlx::query!(
+            "INSERT IGNORE INTO revoked_token (id, user_id, expires_at)
+SELECT access_token_id, user_id, access_token_expires_at FROM user_session
+WHERE id = ? AND access_token_id IS NOT NULL AND access_token_expires_at > NOW()",
+            self.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE user_session SET revoked = TRUE, refresh_token = NULL WHERE id = ?",
+            self.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.revoked = true;
+        self.refresh_token = None;
+
+        Ok(())
+    }
+
+    /// Revokes every session of the user, logging out all of the devices.
+    pub(crate) async fn revoke_all(user_id: UserId, db: &sqlx::Pool<MySql>) -> Result<()> {
+        let mut tx = db.begin().await?;
+
+        sqlx::query!(
+            "INSERT IGNORE INTO revoked_token (id, user_id, expires_at)
+SELECT access_token_id, user_id, access_token_expires_at FROM user_session
+WHERE user_id = ? AND revoked = FALSE AND
+access_token_id IS NOT NULL AND access_token_expires_at > NOW()",
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE user_session SET revoked = TRUE, refresh_token = NULL WHERE user_id = ?",
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn revoke_on_reuse(&mut self, db: &sqlx::Pool<MySql>) -> Result<()> {
+        warn!("refresh token reuse detected (user: {}, session: {})", self.user_id, self.id);
+
+        self.revoke(db).await
+    }
+
+    pub(crate) fn id(&self) -> SessionId {
+        self.id
+    }
+
+    pub(crate) fn is_revoked(&self) -> bool {
+        self.revoked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn session(refresh_token_issued_at: Option<DateTime<Utc>>) -> Session {
+        Session {
+            id: 1,
+            user_id: 1,
+            refresh_token: Some("latest".to_string()),
+            refresh_token_issued_at,
+            revoked: false,
+            created_at: at(0),
+        }
+    }
+
+    #[test]
+    fn refresh_tokens_up_to_the_latest_are_rotated_out() {
+        let session = session(Some(at(100)));
+
+        assert!(session.issued_before_latest(&Token::unsigned(1, 1, TokenType::Refresh, at(0))));
+        assert!(session.issued_before_latest(&Token::unsigned(1, 1, TokenType::Refresh, at(100))));
+        assert!(!session.issued_before_latest(&Token::unsigned(1, 1, TokenType::Refresh, at(101))));
+        assert!(!session.issued_before_latest(&Token::unsigned(1, 1, TokenType::Refresh, at(-1))));
+    }
+
+    #[test]
+    fn only_refresh_tokens_of_the_session_are_rotated_out() {
+        let session = session(Some(at(100)));
+
+        assert!(!session.issued_before_latest(&Token::unsigned(1, 2, TokenType::Refresh, at(50))));
+        assert!(!session.issued_before_latest(&Token::unsigned(1, 1, TokenType::Access, at(50))));
+    }
+
+    #[test]
+    fn nothing_is_rotated_out_of_a_session_never_refreshed() {
+        let session = session(None);
+
+        assert!(!session.issued_before_latest(&Token::unsigned(1, 1, TokenType::Refresh, at(50))));
+    }
+}
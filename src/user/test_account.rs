@@ -0,0 +1,52 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use sqlx::MySql;
+use tracing::warn;
+
+use crate::Result;
+
+use super::phone::PhoneNumber;
+
+/// An account for app store reviewers and QA which logs in with a fixed code.
+///
+/// Test accounts only work when `TEST_ACCOUNTS_ENABLED` is set, and every use
+/// of them is logged.
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct TestAccount {
+    id: u64,
+    phone: PhoneNumber,
+    code: String,
+    description: String,
+}
+
+impl TestAccount {
+    pub(crate) async fn from_phone(
+        phone: &PhoneNumber,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<Option<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+id,
+phone as `phone: PhoneNumber`,
+code,
+description
+FROM test_account WHERE phone = ?",
+            phone
+        )
+        .fetch_optional(db)
+        .await?)
+    }
+
+    pub(crate) fn audit(&self, action: &str) {
+        warn!("test account {} ({}, {}) {action}", self.id, self.phone, self.description);
+    }
+
+    pub(crate) fn phone(&self) -> &PhoneNumber {
+        &self.phone
+    }
+
+    pub(crate) fn code(&self) -> &str {
+        &self.code
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright 2023. The downtown authors all rights reserved.
+
+use std::{fs::File, sync::Arc, time::Duration};
+
+use axum_typed_multipart::FieldData;
+use chrono::{DateTime, NaiveDate, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use sqlx::{MySql, MySqlExecutor};
+use tempfile::NamedTempFile;
+use tokio::fs;
+use tracing::{error, info, warn};
+
+use crate::{aws::S3Client, AppState, Error, Result};
+
+use super::{
+    account::{User, UserId, VerificationResult},
+    phone::PhoneNumber,
+    IdVerificationType,
+};
+
+pub(crate) type VerificationId = u64;
+
+const VERIFICATION_PHOTO_PATH: &str = "verification_photo/";
+
+/// How long the link to an ID photo given to reviewers stays valid
+pub(crate) const PICTURE_LINK_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// How often the retention job looks for ID photos to delete
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// One submission of an ID photo, kept after the review as the history of
+/// the verification. The current state of a user is the latest submission,
+/// unless its approval has been revoked.
+///
+/// The photo itself is deleted once a decision has been made and the
+/// retention period has passed, or as soon as a newer submission replaces a
+/// pending one.
+#[derive(Debug, Serialize)]
+pub(crate) struct Verification {
+    id: VerificationId,
+    verification_type: IdVerificationType,
+    result: VerificationResult,
+    submitted_at: DateTime<Utc>,
+    reviewed_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl Verification {
+    pub(crate) async fn from_id(id: VerificationId, db: &sqlx::Pool<MySql>) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            "SELECT
+id as `id: VerificationId`,
+verification_type as `verification_type: IdVerificationType`,
+result as `result: VerificationResult`,
+submitted_at,
+reviewed_at,
+revoked_at
+FROM user_verification WHERE id = ?",
+            id
+        )
+        .fetch_optional(db)
+        .await?
+        .ok_or(Error::InvalidRequest)
+    }
+
+    /// Every submission of the user, the latest first.
+    pub(crate) async fn history(user: &User, db: &sqlx::Pool<MySql>) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+id as `id: VerificationId`,
+verification_type as `verification_type: IdVerificationType`,
+result as `result: VerificationResult`,
+submitted_at,
+reviewed_at,
+revoked_at
+FROM user_verification WHERE user_id = ? ORDER BY id DESC",
+            user.id()
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    /// Adds a submission waiting for a review. A pending submission of the
+    /// user is replaced, and its photo is deleted.
+    pub(crate) async fn submit(
+        user: &User,
+        verification_type: IdVerificationType,
+        picture: FieldData<NamedTempFile<File>>,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<Self> {
+        if user.is_verified() {
+            return Err(Error::InvalidRequest);
+        }
+
+        let replaced = sqlx::query!(
+            "SELECT id as `id: VerificationId`, picture_key as `picture_key!` FROM user_verification
+WHERE user_id = ? AND result = ? AND picture_key IS NOT NULL",
+            user.id(),
+            VerificationResult::NotVerified
+        )
+        .fetch_all(db)
+        .await?;
+
+        let picture_key = Self::upload_picture(picture, s3).await?;
+
+        let id = sqlx::query!(
+            "INSERT INTO user_verification (user_id, verification_type, picture_key) VALUES (?, ?, ?)",
+            user.id(),
+            verification_type,
+            picture_key
+        )
+        .execute(db)
+        .await?
+        .last_insert_id();
+
+        // Photos left behind here are deleted by the retention job later on
+        for row in replaced {
+            if let Err(err) = Self::delete_picture(row.id, &row.picture_key, db, s3).await {
+                warn!("failed to delete the replaced verification picture {}: {err:?}", row.id);
+            }
+        }
+
+        Self::from_id(id, db).await
+    }
+
+    /// Withdraws the approval of the user, who has to verify again.
+    pub(crate) async fn revoke<'e>(
+        executor: impl MySqlExecutor<'e>,
+        user_id: UserId,
+    ) -> Result<()> {
+        sqlx::query!(
+            "UPDATE user_verification SET revoked_at = NOW()
+WHERE user_id = ? AND result = ? AND revoked_at IS NULL",
+            user_id,
+            VerificationResult::Verified
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Runs the retention rules periodically in the background.
+    pub(crate) fn spawn_retention_job(state: Arc<AppState>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                if let Err(err) = Self::apply_retention(
+                    state.config.verification_picture_retention_days(),
+                    &state.database,
+                    &state.s3,
+                )
+                .await
+                {
+                    error!("failed to apply the verification picture retention: {err:?}");
+                }
+            }
+        });
+    }
+
+    /// Deletes the photos of submissions reviewed more than `retention_days`
+    /// days ago, and of pending submissions replaced by a newer one.
+    pub(crate) async fn apply_retention(
+        retention_days: i64,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<()> {
+        let expired = sqlx::query!(
+            "SELECT id as `id: VerificationId`, picture_key as `picture_key!`
+FROM user_verification as uv WHERE picture_key IS NOT NULL AND (
+(result <> ? AND reviewed_at < NOW() - INTERVAL ? DAY) OR
+(result = ? AND id < (SELECT MAX(id) FROM user_verification WHERE user_id = uv.user_id)))",
+            VerificationResult::NotVerified,
+            retention_days,
+            VerificationResult::NotVerified
+        )
+        .fetch_all(db)
+        .await?;
+
+        for row in expired {
+            // A failed photo is retried on the next run, so keep going with the others
+            match Self::delete_picture(row.id, &row.picture_key, db, s3).await {
+                Ok(()) => info!("deleted the picture of verification {}", row.id),
+                Err(err) => {
+                    error!("failed to delete the picture of verification {}: {err:?}", row.id)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes every photo the user has submitted.
+    pub(crate) async fn delete_pictures(
+        user_id: UserId,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<Vec<String>> {
+        let rows = sqlx::query!(
+            "SELECT id as `id: VerificationId`, picture_key as `picture_key!` FROM user_verification
+WHERE user_id = ? AND picture_key IS NOT NULL",
+            user_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        let mut keys = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            Self::delete_picture(row.id, &row.picture_key, db, s3).await?;
+            keys.push(row.picture_key);
+        }
+
+        Ok(keys)
+    }
+
+    pub(crate) fn id(&self) -> VerificationId {
+        self.id
+    }
+
+    pub(crate) fn verification_type(&self) -> IdVerificationType {
+        self.verification_type
+    }
+
+    /// The key is only cleared once the file is gone, so that a failed
+    /// deletion is retried.
+    async fn delete_picture(
+        id: VerificationId,
+        key: &str,
+        db: &sqlx::Pool<MySql>,
+        s3: &S3Client,
+    ) -> Result<()> {
+        s3.delete_file(key).await?;
+
+        sqlx::query!(
+            "UPDATE user_verification SET picture_key = NULL, picture_deleted_at = NOW()
+WHERE id = ?",
+            id
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn upload_picture(
+        photo: FieldData<NamedTempFile<File>>,
+        s3: &S3Client,
+    ) -> Result<String> {
+        let basename: String =
+            rand::thread_rng().sample_iter(Alphanumeric).take(32).map(char::from).collect();
+        let dir = std::env::temp_dir().join(std::env!("CARGO_PKG_NAME"));
+        let temp_path = dir.join(&basename);
+        let key = String::from(VERIFICATION_PHOTO_PATH) + &basename;
+
+        fs::create_dir_all(&dir)
+            .await
+            .map_err(|err| Error::Io { path: dir.to_path_buf(), source: err })?;
+        photo
+            .contents
+            .persist(&temp_path)
+            .map_err(|err| Error::PersistFile { path: temp_path.clone(), source: err.into() })?;
+
+        s3.push_file(&temp_path, &key).await?;
+
+        Ok(key)
+    }
+}
+
+/// The latest submission of a user, waiting for a review by an admin.
+#[derive(Debug, Serialize)]
+pub(crate) struct PendingVerification {
+    verification_id: VerificationId,
+    user_id: UserId,
+    name: String,
+    birthdate: NaiveDate,
+    #[serde(skip)]
+    phone: PhoneNumber,
+    verification_type: IdVerificationType,
+    #[serde(skip)]
+    picture_key: String,
+    submitted_at: DateTime<Utc>,
+}
+
+impl PendingVerification {
+    /// Returns the oldest submissions first.
+    pub(crate) async fn get(limit: i32, db: &sqlx::Pool<MySql>) -> Result<Vec<Self>> {
+        Ok(sqlx::query_as!(
+            Self,
+            "SELECT
+uv.id as `verification_id: VerificationId`,
+u.id as `user_id: UserId`,
+u.name,
+u.birthdate,
+u.phone as `phone: PhoneNumber`,
+uv.verification_type as `verification_type: IdVerificationType`,
+uv.picture_key as `picture_key!`,
+uv.submitted_at
+FROM user_verification as uv JOIN user as u ON u.id = uv.user_id WHERE
+u.deleted = FALSE AND uv.result = ? AND uv.picture_key IS NOT NULL AND
+uv.id = (SELECT MAX(id) FROM user_verification WHERE user_id = uv.user_id)
+ORDER BY uv.submitted_at ASC LIMIT ?",
+            VerificationResult::NotVerified,
+            limit
+        )
+        .fetch_all(db)
+        .await?)
+    }
+
+    pub(crate) async fn from_user_id(user_id: UserId, db: &sqlx::Pool<MySql>) -> Result<Self> {
+        sqlx::query_as!(
+            Self,
+            "SELECT
+uv.id as `verification_id: VerificationId`,
+u.id as `user_id: UserId`,
+u.name,
+u.birthdate,
+u.phone as `phone: PhoneNumber`,
+uv.verification_type as `verification_type: IdVerificationType`,
+uv.picture_key as `picture_key!`,
+uv.submitted_at
+FROM user_verification as uv JOIN user as u ON u.id = uv.user_id WHERE
+u.id = ? AND u.deleted = FALSE AND uv.result = ? AND uv.picture_key IS NOT NULL AND
+uv.id = (SELECT MAX(id) FROM user_verification WHERE user_id = uv.user_id)",
+            user_id,
+            VerificationResult::NotVerified
+        )
+        .fetch_optional(db)
+        .await?
+        .ok_or(Error::VerificationNotFound(user_id))
+    }
+
+    /// Creates a short-lived link to the ID photo for the reviewer.
+    pub(crate) async fn picture_link(&self, s3: &S3Client) -> Result<String> {
+        s3.presigned_url(&self.picture_key, PICTURE_LINK_LIFETIME).await
+    }
+
+    /// Approves the verification with `Verified`, or rejects it with any
+    /// other reason.
+    pub(crate) async fn review(
+        self,
+        reviewer: &User,
+        result: VerificationResult,
+        db: &sqlx::Pool<MySql>,
+    ) -> Result<()> {
+        if matches!(result, VerificationResult::NotVerified) {
+            return Err(Error::InvalidRequest);
+        }
+
+        // Only the first of concurrent reviews takes effect
+        let reviewed = sqlx::query!(
+            "UPDATE user_verification SET result = ?, reviewer_id = ?, reviewed_at = NOW()
+WHERE id = ? AND result = ? AND picture_key IS NOT NULL",
+            result,
+            reviewer.id(),
+            self.verification_id,
+            VerificationResult::NotVerified
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        match reviewed {
+            0 => Err(Error::VerificationNotFound(self.user_id)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn phone(&self) -> &PhoneNumber {
+        &self.phone
+    }
+}
+
+/// Notification sent to the user once the review is done.
+pub(crate) fn review_message(result: VerificationResult) -> String {
+    let reason = match result {
+        VerificationResult::Verified => return String::from("이프 본인 인증이 완료되었습니다."),
+        VerificationResult::NotVerified => "검토 중",
+        VerificationResult::InvalidPicture => "올바르지 않은 사진",
+        VerificationResult::LowQualityPicture => "사진 화질 불량",
+        VerificationResult::NonMaskedIdCard => "신분증 주민등록번호 뒷자리 미가림",
+        VerificationResult::NonMaskedDriverLicense => "운전면허증 면허번호 미가림",
+        VerificationResult::NonMaskedAll => "신분증 개인정보 미가림",
+        VerificationResult::NonResident => "거주지 불일치",
+    };
+
+    format!("이프 본인 인증이 반려되었습니다. (사유: {reason}) 다시 제출해주세요.")
+}